@@ -0,0 +1,185 @@
+//! Offline renderer: builds the scene, bakes the lightmaps and writes a single frame to an image file.
+//! Doesn't touch SDL, so it runs on machines without a display.
+//!
+//! Output ending in `.hdr`, `.pfm` or `.exr` gets the linear frame, before any post-processing. Anything else gets
+//! the finished 8-bit image.
+//!
+//! Camera and supersampling options override the scene file. Camera options are applied in the order position,
+//! look-at, then angles.
+
+use std::process::exit;
+use std::str::FromStr;
+use std::time::Instant;
+
+use ray_marching::engine::{RenderTarget, DEFAULT_HEIGHT, DEFAULT_WIDTH};
+use ray_marching::output::{save_bgra, save_hdr, HdrFormat};
+use ray_marching::sampling::{ReconstructionFilter, SamplePattern};
+use ray_marching::scene::{load_scene, DEFAULT_SCENE};
+use ray_marching::stats::Profiler;
+use ray_marching::vector::Vec3;
+
+#[global_allocator]
+static GLOBAL: mimalloc::MiMalloc = mimalloc::MiMalloc;
+
+const USAGE: &str = "usage: render [--scene FILE] [--out FILE] [--width W] [--height H] [--samples N]
+              [--ssaa N] [--pattern grid|rotated_grid|stratified|n_rooks] [--filter box|tent|mitchell]
+              [--camera X,Y,Z] [--look-at X,Y,Z] [--yaw DEG] [--pitch DEG] [--roll DEG] [--fov DEG]
+              [--stats-csv FILE]";
+
+struct Options {
+    scene:   String,
+    out:     String,
+    width:   usize,
+    height:  usize,
+    /// jittered frames averaged together
+    samples: u32,
+    ssaa:    Option<u32>,
+    pattern: Option<SamplePattern>,
+    filter:  Option<ReconstructionFilter>,
+    camera:  Option<Vec3>,
+    look_at: Option<Vec3>,
+    yaw:     Option<f32>,
+    pitch:   Option<f32>,
+    roll:    Option<f32>,
+    fov:     Option<f32>,
+    /// where to log the timings of every frame
+    csv:     Option<String>,
+}
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    exit(2);
+}
+
+fn parse_f32(value: &str) -> f32 { value.trim().parse().unwrap_or_else(|_| usage()) }
+
+fn parse_name<T: FromStr<Err = String>>(value: &str) -> T {
+    value.parse().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        usage()
+    })
+}
+
+fn parse_vec3(value: &str) -> Vec3 {
+    let xyz: Vec<f32> = value.split(',').map(parse_f32).collect();
+    if xyz.len() != 3 {
+        usage();
+    }
+    Vec3::new(xyz[0], xyz[1], xyz[2])
+}
+
+fn parse_options() -> Options {
+    let mut options = Options {
+        scene:   DEFAULT_SCENE.to_string(),
+        out:     "render.png".to_string(),
+        width:   DEFAULT_WIDTH,
+        height:  DEFAULT_HEIGHT,
+        samples: 1,
+        ssaa:    None,
+        pattern: None,
+        filter:  None,
+        camera:  None,
+        look_at: None,
+        yaw:     None,
+        pitch:   None,
+        roll:    None,
+        fov:     None,
+        csv:     None,
+    };
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let value = args.next().unwrap_or_else(|| usage());
+        match arg.as_str() {
+            "--scene" => options.scene = value,
+            "--out" => options.out = value,
+            "--width" => options.width = value.parse().unwrap_or_else(|_| usage()),
+            "--height" => options.height = value.parse().unwrap_or_else(|_| usage()),
+            "--samples" => options.samples = value.parse().unwrap_or_else(|_| usage()),
+            "--ssaa" => options.ssaa = Some(value.parse().unwrap_or_else(|_| usage())),
+            "--pattern" => options.pattern = Some(parse_name(&value)),
+            "--filter" => options.filter = Some(parse_name(&value)),
+            "--camera" => options.camera = Some(parse_vec3(&value)),
+            "--look-at" => options.look_at = Some(parse_vec3(&value)),
+            "--yaw" => options.yaw = Some(parse_f32(&value).to_radians()),
+            "--pitch" => options.pitch = Some(parse_f32(&value).to_radians()),
+            "--roll" => options.roll = Some(parse_f32(&value).to_radians()),
+            "--fov" => options.fov = Some(parse_f32(&value)),
+            "--stats-csv" => options.csv = Some(value),
+            _ => usage(),
+        }
+    }
+    if options.width == 0 || options.height == 0 || options.samples == 0 {
+        usage();
+    }
+    options
+}
+
+fn main() {
+    let options = parse_options();
+
+    let mut engine = load_scene(&options.scene).unwrap_or_else(|e| {
+        eprintln!("{}: {}", options.scene, e);
+        exit(1);
+    });
+
+    let camera = &mut engine.camera;
+    if let Some(position) = options.camera {
+        camera.position = position;
+    }
+    if let Some(target) = options.look_at {
+        if target - camera.position == Vec3::default() {
+            eprintln!("--look-at can't be the camera's position");
+            exit(1);
+        }
+        camera.point_along(target - camera.position);
+    }
+    camera.yaw = options.yaw.unwrap_or(camera.yaw);
+    camera.pitch = options.pitch.unwrap_or(camera.pitch);
+    camera.roll = options.roll.unwrap_or(camera.roll);
+    camera.fov = options.fov.unwrap_or(camera.fov);
+
+    let ssaa = &mut engine.ssaa;
+    ssaa.samples = options.ssaa.unwrap_or(ssaa.samples);
+    ssaa.pattern = options.pattern.unwrap_or(ssaa.pattern);
+    ssaa.filter = options.filter.unwrap_or(ssaa.filter);
+
+    let now = Instant::now();
+    engine.compute_lightmaps();
+    println!("Lightmaps: {:.2?}", now.elapsed());
+
+    let (width, height) = (options.width, options.height);
+    let mut target = RenderTarget::new(width, height);
+    let mut buffer = vec![0u8; width * height * 4];
+
+    let mut profiler = Profiler::new(options.samples as usize);
+    if let Some(path) = &options.csv {
+        if let Err(e) = profiler.log_csv(path) {
+            eprintln!("{}: {}", path, e);
+            exit(1);
+        }
+    }
+
+    let now = Instant::now();
+    let mut stats = Default::default();
+    for _ in 0..options.samples {
+        let frame_start = Instant::now();
+        stats = engine.render(&mut buffer, width * 4, &mut target);
+        if let Err(e) = profiler.record(stats.times, frame_start.elapsed()) {
+            eprintln!("stopped writing stats: {}", e);
+        }
+    }
+    println!("Render: {:.2?}", now.elapsed());
+    println!("Bounce depth: {:.2} mean, {} max", stats.mean_depth(), stats.max_depth);
+    print!("{}", profiler.summary());
+
+    let result = match HdrFormat::from_path(&options.out) {
+        Some(format) => save_hdr(&options.out, &target.linear_frame(), format),
+        None => save_bgra(&options.out, &buffer, width * 4, width, height),
+    };
+    if let Err(e) = result {
+        eprintln!("failed to write {}: {}", options.out, e);
+        exit(1);
+    }
+    println!("Wrote {}", options.out);
+}
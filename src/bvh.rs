@@ -0,0 +1,267 @@
+use crate::engine::ObjectRef;
+use crate::vector::Vec3;
+
+// deep enough for any tree built by splitting at the median
+const MAX_STACK_DEPTH: usize = 64;
+// most objects in a leaf, testing a box isn't worth it for fewer than this
+const MAX_LEAF_OBJECTS: usize = 4;
+
+/// Axis aligned bounding box
+#[derive(Clone, Copy, Debug)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    pub fn new(min: Vec3, max: Vec3) -> Self { Self { min, max } }
+
+    /// Box containing a sphere
+    pub fn around(centre: Vec3, radius: f32) -> Self {
+        let r = Vec3::new(radius, radius, radius);
+        Self::new(centre - r, centre + r)
+    }
+
+    /// Grown by `by` on every side
+    pub fn expanded(&self, by: Vec3) -> Aabb { Aabb::new(self.min - by, self.max + by) }
+
+    pub fn union(&self, other: &Aabb) -> Aabb { Aabb::new(self.min.min(other.min), self.max.max(other.max)) }
+
+    pub fn centre(&self) -> Vec3 { (self.min + self.max) * 0.5 }
+
+    /// Distance from a point to the box, 0 inside it. Never more than the distance to anything inside the box
+    pub fn distance(&self, position: Vec3) -> f32 {
+        let outside = (self.min - position).max(position - self.max).max(Vec3::default());
+        outside.mag()
+    }
+}
+
+enum BvhNode {
+    /// objects[start..end] of the bvh's object list
+    Leaf {
+        bounds: Aabb,
+        start:  usize,
+        end:    usize,
+    },
+    Branch {
+        bounds: Aabb,
+        left:   usize,
+        right:  usize,
+    },
+}
+
+impl BvhNode {
+    fn bounds(&self) -> &Aabb {
+        match self {
+            BvhNode::Leaf { bounds, .. } | BvhNode::Branch { bounds, .. } => bounds,
+        }
+    }
+}
+
+/// Bounding volume hierarchy over the objects in the scene, so marching only evaluates the SDFs of nearby objects.
+/// Objects without bounds, like planes, are always evaluated.
+#[derive(Default)]
+pub struct Bvh {
+    nodes:     Vec<BvhNode>,
+    objects:   Vec<usize>,
+    unbounded: Vec<usize>,
+}
+
+impl Bvh {
+    pub fn new(objects: &[ObjectRef]) -> Self {
+        let mut bvh = Bvh::default();
+        let mut bounded = Vec::new();
+
+        for (i, object) in objects.iter().enumerate() {
+            match object.bounds() {
+                Some(bounds) => bounded.push((i, bounds)),
+                None => bvh.unbounded.push(i),
+            }
+        }
+
+        if !bounded.is_empty() {
+            bvh.build(&mut bounded, 0);
+        }
+        bvh.objects = bounded.iter().map(|(i, _)| *i).collect();
+        bvh
+    }
+
+    /// Split the objects at the median of their centres along the widest axis, returning the new node's index.
+    /// `items` is sorted in place so every leaf's objects are contiguous, `start` is where `items` begins in it
+    fn build(&mut self, items: &mut [(usize, Aabb)], start: usize) -> usize {
+        let index = self.nodes.len();
+        let bounds = items.iter().skip(1).fold(items[0].1, |acc, (_, b)| acc.union(b));
+
+        if items.len() <= MAX_LEAF_OBJECTS {
+            self.nodes.push(BvhNode::Leaf {
+                bounds,
+                start,
+                end: start + items.len(),
+            });
+            return index;
+        }
+
+        let mut centre_min = Vec3::new(f32::INFINITY, f32::INFINITY, f32::INFINITY);
+        let mut centre_max = -centre_min;
+        for (_, b) in items.iter() {
+            centre_min = centre_min.min(b.centre());
+            centre_max = centre_max.max(b.centre());
+        }
+        let extent = centre_max - centre_min;
+        let axis = if extent.x() > extent.y() && extent.x() > extent.z() {
+            0
+        } else if extent.y() > extent.z() {
+            1
+        } else {
+            2
+        };
+
+        items.sort_by(|a, b| a.1.centre().0[axis].total_cmp(&b.1.centre().0[axis]));
+        let mid = items.len() / 2;
+
+        // children are filled in once they're built
+        self.nodes.push(BvhNode::Branch {
+            bounds,
+            left: 0,
+            right: 0,
+        });
+        let (left_items, right_items) = items.split_at_mut(mid);
+        let left_index = self.build(left_items, start);
+        let right_index = self.build(right_items, start + mid);
+        if let BvhNode::Branch { left, right, .. } = &mut self.nodes[index] {
+            *left = left_index;
+            *right = right_index;
+        }
+        index
+    }
+
+    /// The smallest SDF value at `position` among objects that `include` accepts, and the index of that object.
+    /// Objects whose bounds are further away than the closest object found so far are skipped
+    #[inline]
+    pub fn closest<F: Fn(usize) -> bool>(&self, objects: &[ObjectRef], position: Vec3, include: F) -> (f32, usize) {
+        let mut closest = (f32::INFINITY, usize::MAX);
+        let test_objects = |indexes: &[usize], closest: &mut (f32, usize)| {
+            for &i in indexes {
+                if !include(i) {
+                    continue;
+                }
+                let obj_distance = objects[i].sdf(position);
+                if obj_distance < closest.0 {
+                    *closest = (obj_distance, i);
+                }
+            }
+        };
+
+        test_objects(&self.unbounded, &mut closest);
+
+        // no point testing the root's bounds if it's a leaf
+        match self.nodes.first() {
+            None => return closest,
+            Some(BvhNode::Leaf { start, end, .. }) => {
+                test_objects(&self.objects[*start..*end], &mut closest);
+                return closest;
+            }
+            Some(BvhNode::Branch { bounds, .. }) => {
+                // nodes still to visit, with the distance to their bounds
+                let mut stack = [(0.0, 0usize); MAX_STACK_DEPTH];
+                stack[0] = (bounds.distance(position), 0);
+                let mut stack_len = 1;
+
+                while stack_len > 0 {
+                    stack_len -= 1;
+                    let (bounds_distance, node_index) = stack[stack_len];
+
+                    if bounds_distance >= closest.0 {
+                        continue;
+                    }
+
+                    match self.nodes[node_index] {
+                        BvhNode::Leaf { start, end, .. } => test_objects(&self.objects[start..end], &mut closest),
+                        BvhNode::Branch { left, right, .. } => {
+                            let left = (self.nodes[left].bounds().distance(position), left);
+                            let right = (self.nodes[right].bounds().distance(position), right);
+
+                            // visit the nearer child first, it's more likely to shrink the distance and prune the other
+                            let (near, far) = if left.0 <= right.0 {
+                                (left, right)
+                            } else {
+                                (right, left)
+                            };
+                            stack[stack_len] = far;
+                            stack[stack_len + 1] = near;
+                            stack_len += 2;
+                        }
+                    }
+                }
+            }
+        }
+
+        closest
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::colour::WHITE;
+    use crate::material::Material;
+    use crate::objects::{Sphere, XPlane, YPlane};
+
+    /// Numbers from -1 to 1, the same every run
+    fn random(seed: &mut u32) -> f32 {
+        *seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+        (*seed >> 8) as f32 / (1 << 23) as f32 - 1.0
+    }
+
+    fn scene() -> Vec<ObjectRef> {
+        let mut seed = 1;
+        let mut objects: Vec<ObjectRef> = (0..40)
+            .map(|_| {
+                let position = Vec3::new(random(&mut seed), random(&mut seed), random(&mut seed)) * 5.0;
+                let object: ObjectRef = Box::new(Sphere {
+                    position,
+                    radius: 0.2 + random(&mut seed).abs(),
+                    material: Material::basic(),
+                    colour: WHITE,
+                    lightmap: Default::default(),
+                });
+                object
+            })
+            .collect();
+        objects.insert(7, Box::new(YPlane::new(-4.0, 1.0, Material::basic(), WHITE, None)));
+        objects.push(Box::new(XPlane::new(6.0, -1.0, Material::basic(), WHITE)));
+        objects
+    }
+
+    /// Closest object by testing every one of them
+    fn brute_force<F: Fn(usize) -> bool>(objects: &[ObjectRef], position: Vec3, include: F) -> f32 {
+        (0..objects.len())
+            .filter(|&i| include(i))
+            .map(|i| objects[i].sdf(position))
+            .fold(f32::INFINITY, f32::min)
+    }
+
+    #[test]
+    fn closest_matches_brute_force() {
+        let objects = scene();
+        let bvh = Bvh::new(&objects);
+        let mut seed = 2;
+        for _ in 0..1000 {
+            let position = Vec3::new(random(&mut seed), random(&mut seed), random(&mut seed)) * 8.0;
+            let (distance, index) = bvh.closest(&objects, position, |_| true);
+            assert_eq!(distance, brute_force(&objects, position, |_| true));
+            assert_eq!(distance, objects[index].sdf(position));
+
+            // skipping the closest object still finds the next one
+            let (distance, _) = bvh.closest(&objects, position, |i| i != index);
+            assert_eq!(distance, brute_force(&objects, position, |i| i != index));
+        }
+    }
+
+    #[test]
+    fn only_unbounded_objects() {
+        let objects: Vec<ObjectRef> = vec![Box::new(YPlane::new(-1.0, 1.0, Material::basic(), WHITE, None))];
+        let bvh = Bvh::new(&objects);
+        assert_eq!(bvh.closest(&objects, Vec3::new(0.0, 2.0, 0.0), |_| true), (3.0, 0));
+    }
+}
@@ -0,0 +1,76 @@
+use super::vector::Vec3;
+
+pub const DEFAULT_FOV: f32 = 90.0;
+
+/// A pinhole camera. Angles are in radians, except the field of view which is in degrees.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Camera {
+    pub position: Vec3,
+    /// rotation about the y axis, positive turns right
+    pub yaw:      f32,
+    /// rotation above the horizon, positive looks up
+    pub pitch:    f32,
+    /// rotation about the view direction, positive tilts clockwise
+    pub roll:     f32,
+    /// vertical field of view
+    pub fov:      f32,
+}
+
+impl Camera {
+    /// A camera at `position` looking down +z
+    pub fn new(position: Vec3) -> Self {
+        Self {
+            position,
+            yaw: 0.0,
+            pitch: 0.0,
+            roll: 0.0,
+            fov: DEFAULT_FOV,
+        }
+    }
+
+    /// A camera at `position` pointing at `target`
+    pub fn look_at(position: Vec3, target: Vec3) -> Self { Self::look_along(position, target - position) }
+
+    /// A camera at `position` pointing along `direction`
+    pub fn look_along(position: Vec3, direction: Vec3) -> Self {
+        let mut camera = Self::new(position);
+        camera.point_along(direction);
+        camera
+    }
+
+    /// Turn the camera to face along `direction`, keeping roll and fov. A zero direction leaves it facing the same way
+    pub fn point_along(&mut self, direction: Vec3) {
+        if direction.mag_sqd() == 0.0 {
+            return;
+        }
+        let d = direction.normalized();
+        self.yaw = d.x().atan2(d.z());
+        self.pitch = d.y().clamp(-1.0, 1.0).asin();
+    }
+
+    /// The camera's right, up and forward vectors
+    pub fn basis(&self) -> (Vec3, Vec3, Vec3) {
+        let (sy, cy) = self.yaw.sin_cos();
+        let (sp, cp) = self.pitch.sin_cos();
+        let (sr, cr) = self.roll.sin_cos();
+
+        let forward = Vec3::new(sy * cp, sp, cy * cp);
+        let right = Vec3::new(cy, 0.0, -sy);
+        let up = Vec3::new(-sy * sp, cp, -cy * sp);
+
+        (right * cr - up * sr, up * cr + right * sr, forward)
+    }
+
+    /// Distance from the eye to an image plane spanning -1..1 vertically
+    pub fn focal_length(&self) -> f32 { (self.fov * 0.5).to_radians().tan().recip() }
+
+    /// Move by `offset`, given in camera space (x right, y up, z forward)
+    pub fn move_relative(&mut self, offset: Vec3) {
+        let (right, up, forward) = self.basis();
+        self.position += right * offset.x() + up * offset.y() + forward * offset.z();
+    }
+}
+
+impl Default for Camera {
+    fn default() -> Self { Camera::new(Vec3::default()) }
+}
@@ -3,17 +3,46 @@ use super::vector::Vec3;
 
 pub type Colour = Vec3;
 
+/// Linear colour from 8-bit sRGB values
 #[macro_export]
 macro_rules! rgb {
     [$r:expr, $g:expr, $b:expr] => {
         Colour::new(
-             ($r as f32 / 255.0) * ($r as f32 / 255.0),
-             ($g as f32 / 255.0) * ($g as f32 / 255.0),
-             ($b as f32 / 255.0) * ($b as f32 / 255.0),
+             $crate::colour::srgb_to_linear($r as f32 / 255.0),
+             $crate::colour::srgb_to_linear($g as f32 / 255.0),
+             $crate::colour::srgb_to_linear($b as f32 / 255.0),
         )
     };
 }
 
+/// sRGB decode of a value from 0 to 1. `const` so colour constants can use it, powf isn't, so the 2.4 power is
+/// x^2 times the fifth root of x^2, found with Newton's method
+pub const fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        return c / 12.92;
+    }
+    let x = ((c as f64 + 0.055) / 1.055).min(1.0);
+    let x2 = x * x;
+    // x2 is at least 0.008, so starting from 1 this has converged well before the end
+    let mut root = 1.0;
+    let mut i = 0;
+    while i < 16 {
+        root = (4.0 * root + x2 / (root * root * root * root)) / 5.0;
+        i += 1;
+    }
+    (x2 * root) as f32
+}
+
+/// sRGB encode of a linear value, clamped to 0 to 1
+pub fn linear_to_srgb(c: f32) -> f32 {
+    let c = c.clamp(0.0, 1.0);
+    if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
 pub const WHITE: Colour = rgb![255, 255, 255];
 pub const SOFT_RED: Colour = rgb![214, 81, 81];
 pub const SOFT_GREEN: Colour = rgb![81, 214, 81];
@@ -43,6 +72,66 @@ pub fn ACESFilm(mut col: Colour) -> Colour {
     col
 }
 
+/// Relative luminance of a linear colour, Rec. 709 weights
+pub fn luminance(colour: Colour) -> f32 { colour.dot(Vec3::new(0.2126, 0.7152, 0.0722)) }
+
+/// Reinhard on luminance, so hues are kept. `white` is the luminance that maps to 1, infinite for the original curve
+pub fn reinhard(col: Colour, white: f32) -> Colour {
+    let l = luminance(col);
+    if l <= 0.0 {
+        return Colour::default();
+    }
+    let mapped = l * (1.0 + l / (white * white)) / (1.0 + l);
+    col * (mapped / l)
+}
+
+/// John Hable's filmic curve from Uncharted 2
+pub fn hable(col: Colour) -> Colour {
+    const EXPOSURE_BIAS: f32 = 2.0;
+    // linear white point
+    const W: f32 = 11.2;
+    let curve = |x: f32| {
+        let (a, b, c, d, e, f) = (0.15, 0.50, 0.10, 0.20, 0.02, 0.30);
+        ((x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f)) - e / f
+    };
+    let scale = curve(W).recip();
+    Colour::new(
+        curve(col.x() * EXPOSURE_BIAS) * scale,
+        curve(col.y() * EXPOSURE_BIAS) * scale,
+        curve(col.z() * EXPOSURE_BIAS) * scale,
+    )
+}
+
+/// AgX, using the polynomial fit of its contrast curve. Desaturates bright colours towards white instead of
+/// skewing their hue
+pub fn agx(col: Colour) -> Colour {
+    const MIN_EV: f32 = -12.473_93;
+    const MAX_EV: f32 = 4.026_069;
+    // columns of the inset and outset matrices
+    const INSET: [Vec3; 3] = [
+        Vec3::new(0.842_479_1, 0.042_328_24, 0.042_375_65),
+        Vec3::new(0.078_433_6, 0.878_468_6, 0.078_433_6),
+        Vec3::new(0.079_223_75, 0.079_166_13, 0.879_143),
+    ];
+    const OUTSET: [Vec3; 3] = [
+        Vec3::new(1.196_879, -0.052_896_85, -0.052_971_64),
+        Vec3::new(-0.098_020_88, 1.151_903_1, -0.098_043_45),
+        Vec3::new(-0.099_029_74, -0.098_961_18, 1.151_073_7),
+    ];
+    let transform = |m: &[Vec3; 3], v: Vec3| m[0] * v.x() + m[1] * v.y() + m[2] * v.z();
+    let contrast = |x: f32| {
+        let x = ((x.max(1e-10).log2().clamp(MIN_EV, MAX_EV)) - MIN_EV) / (MAX_EV - MIN_EV);
+        let (x2, x4) = (x * x, x * x * x * x);
+        15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x - 0.00232
+    };
+
+    let v = transform(&INSET, col);
+    let v = transform(&OUTSET, Colour::new(contrast(v.x()), contrast(v.y()), contrast(v.z())));
+    // the curve's output is display encoded, take it back to linear for the output transfer
+    let linear = |x: f32| srgb_to_linear(x.max(0.0));
+    Colour::new(linear(v.x()), linear(v.y()), linear(v.z()))
+}
+
 // interpolates (x,y) between the 4 points. The 4 points should form a rectangle
 pub fn bilinear_interpolation(x: f32, y: f32, points: &mut [(f32, f32, Colour); 4]) -> Colour {
     /*sort by y values, then x, to get 00,01,10,11 order */
@@ -70,22 +159,19 @@ pub fn bilinear_interpolation(x: f32, y: f32, points: &mut [(f32, f32, Colour);
         / ((x2 - x1) * (y2 - y1))
 }
 
-// Phong diffuse and specular shading
+// Phong diffuse and specular shading, light_intensity is the coloured intensity after falloff
 pub fn phong_ds(
-    n: Colour, vector_to_light: Colour, distance_to_light: f32, light_intensity: f32, object_mat: &Material,
-    view_direction: Colour,
-) -> (f32, f32) {
+    n: Colour, vector_to_light: Colour, light_intensity: Colour, object_mat: &Material, view_direction: Colour,
+) -> (Colour, Colour) {
     let diffuse: f32;
     let specular: f32;
 
     let light_reflection_vector = vector_to_light.reflect(n);
-    let light_intensity = light_intensity / (distance_to_light).powi(2); // k/d^2
 
     // Phong shading algorithm
-    diffuse = object_mat.diffuse * light_intensity * vector_to_light.dot(n).max(0.0);
+    diffuse = object_mat.diffuse * vector_to_light.dot(n).max(0.0);
     if diffuse > 0.0 {
         specular = object_mat.specular
-            * light_intensity
             * light_reflection_vector
                 .dot(view_direction)
                 .max(0.0)
@@ -94,5 +180,64 @@ pub fn phong_ds(
         specular = 0.0;
     }
 
-    (diffuse, specular)
+    (light_intensity * diffuse, light_intensity * specular)
+}
+
+/// Approximate linear colour of a black body at a temperature in Kelvin, brightest channel at 1.
+/// Based on Tanner Helland's fit, good from 1000K to 40000K
+pub fn kelvin_to_rgb(kelvin: f32) -> Colour {
+    let t = kelvin.clamp(1000.0, 40000.0) / 100.0;
+
+    let r = if t <= 66.0 {
+        255.0
+    } else {
+        329.69873 * (t - 60.0).powf(-0.13320476)
+    };
+    let g = if t <= 66.0 {
+        99.4708 * t.ln() - 161.11957
+    } else {
+        288.12216 * (t - 60.0).powf(-0.075514846)
+    };
+    let b = if t >= 66.0 {
+        255.0
+    } else if t <= 19.0 {
+        0.0
+    } else {
+        138.51773 * (t - 10.0).ln() - 305.0448
+    };
+
+    rgb![r.clamp(0.0, 255.0), g.clamp(0.0, 255.0), b.clamp(0.0, 255.0)]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn srgb_reference(c: f32) -> f32 {
+        if c <= 0.04045 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    }
+
+    #[test]
+    fn srgb_round_trip() {
+        for i in 0..=255 {
+            let encoded = i as f32 / 255.0;
+            let linear = srgb_to_linear(encoded);
+            assert!((linear - srgb_reference(encoded)).abs() < 1e-6, "{}", i);
+            assert!((linear_to_srgb(linear) - encoded).abs() < 1e-5, "{}", i);
+        }
+        assert!((srgb_to_linear(0.5) - 0.214_041_14).abs() < 1e-6);
+        assert_eq!(linear_to_srgb(-1.0), 0.0);
+        assert!((linear_to_srgb(2.0) - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn rgb_macro_decodes() {
+        let colour = rgb![255, 128, 0];
+        assert_eq!((colour.x(), colour.z()), (1.0, 0.0));
+        assert!((colour.y() - srgb_reference(128.0 / 255.0)).abs() < 1e-6);
+    }
 }
@@ -0,0 +1,230 @@
+//! Constructive solid geometry. A `Csg` node combines two objects, which can be `Csg` nodes themselves, into a single
+//! object with its own lightmap. Where the two meet they can be blended together with a smooth minimum, and colour
+//! and material are blended along with them.
+
+use crate::bvh::Aabb;
+use crate::colour::Colour;
+use crate::engine::ObjectRef;
+use crate::material::Material;
+use crate::objects::{globe_direction, globe_uv, outer_surface_point, EngineObject};
+use crate::radiosity::Lightmap;
+use crate::vector::Vec3;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Operation {
+    Union,
+    Intersection,
+    /// the first object with the second cut out of it
+    Subtraction,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Blend {
+    /// a hard edge where the objects meet
+    Sharp,
+    /// quadratic smooth minimum, blending over about this distance
+    Polynomial(f32),
+    /// exponential smooth minimum, rounder but it reaches further than its radius
+    Exponential(f32),
+}
+
+impl Blend {
+    /// Smooth minimum of two distances, and how much of it comes from `a` rather than `b`
+    fn min(self, a: f32, b: f32) -> (f32, f32) {
+        match self {
+            Blend::Polynomial(k) if k > 0.0 => {
+                let h = (0.5 + 0.5 * (b - a) / k).clamp(0.0, 1.0);
+                (b + (a - b) * h - k * h * (1.0 - h), h)
+            }
+            Blend::Exponential(k) if k > 0.0 => {
+                // relative to the closer one, so the exponentials can't overflow
+                let closest = a.min(b);
+                let (ea, eb) = ((-(a - closest) / k).exp2(), (-(b - closest) / k).exp2());
+                (closest - k * (ea + eb).log2(), ea / (ea + eb))
+            }
+            _ if a <= b => (a, 1.0),
+            _ => (b, 0.0),
+        }
+    }
+
+    /// How far a blended union can swell past the objects in it
+    fn reach(self) -> f32 {
+        match self {
+            Blend::Sharp => 0.0,
+            Blend::Polynomial(k) => k * 0.25,
+            Blend::Exponential(k) => k,
+        }
+    }
+}
+
+pub struct Csg {
+    pub operation: Operation,
+    pub blend:     Blend,
+    pub a:         ObjectRef,
+    pub b:         ObjectRef,
+    pub lightmap:  Lightmap,
+}
+
+impl Csg {
+    pub fn new(operation: Operation, blend: Blend, a: ObjectRef, b: ObjectRef) -> Self {
+        Self {
+            operation,
+            blend,
+            a,
+            b,
+            lightmap: Lightmap::default(),
+        }
+    }
+
+    /// Distance to the combined surface, and how much of the surface there is `a` rather than `b`
+    fn combine(&self, position: Vec3) -> (f32, f32) {
+        let (a, b) = (self.a.sdf(position), self.b.sdf(position));
+        match self.operation {
+            Operation::Union => self.blend.min(a, b),
+            // both are a smooth maximum
+            Operation::Intersection => {
+                let (distance, weight) = self.blend.min(-a, -b);
+                (-distance, weight)
+            }
+            Operation::Subtraction => {
+                let (distance, weight) = self.blend.min(-a, b);
+                (-distance, weight)
+            }
+        }
+    }
+}
+
+impl EngineObject for Csg {
+    fn sdf(&self, position: Vec3) -> f32 { self.combine(position).0 }
+
+    fn bounds(&self) -> Option<Aabb> {
+        match self.operation {
+            Operation::Union => {
+                let reach = self.blend.reach();
+                let bounds = self.a.bounds()?.union(&self.b.bounds()?);
+                Some(bounds.expanded(Vec3::new(reach, reach, reach)))
+            }
+            // blending an intersection or subtraction only takes away
+            Operation::Intersection => match (self.a.bounds(), self.b.bounds()) {
+                (Some(a), Some(b)) => Some(Aabb::new(a.min.max(b.min), a.max.min(b.max))),
+                (bounds, None) | (None, bounds) => bounds,
+            },
+            Operation::Subtraction => self.a.bounds(),
+        }
+    }
+
+    fn colour(&self, position: Vec3) -> Colour {
+        match self.combine(position).1 {
+            weight if weight >= 1.0 => self.a.colour(position),
+            weight if weight <= 0.0 => self.b.colour(position),
+            weight => self.a.colour(position) * weight + self.b.colour(position) * (1.0 - weight),
+        }
+    }
+
+    fn material(&self) -> &Material { self.a.material() }
+    fn material_at(&self, position: Vec3) -> Material {
+        match self.combine(position).1 {
+            weight if weight >= 1.0 => self.a.material_at(position),
+            weight if weight <= 0.0 => self.b.material_at(position),
+            weight => self
+                .a
+                .material_at(position)
+                .lerp(&self.b.material_at(position), 1.0 - weight),
+        }
+    }
+
+    fn radiosity_collide(&self) -> bool { self.a.radiosity_collide() || self.b.radiosity_collide() }
+
+    // the whole tree shares one lightmap, wrapped around it like a globe. without bounds there's nothing to wrap
+    fn get_lightmap(&self) -> Option<&Lightmap> { self.bounds().map(|_| &self.lightmap) }
+    fn set_lightmap(&mut self, new_lightmap: Lightmap) { self.lightmap = new_lightmap; }
+    fn clear_lightmap(&mut self) { self.lightmap = Lightmap::default() }
+
+    fn get_sample_pos(&self, u: usize, v: usize) -> Vec3 {
+        outer_surface_point(self, self.bounds().unwrap(), globe_direction(u, v))
+    }
+    fn sample_uv_from_pos(&self, pos: Vec3) -> (f32, f32) { globe_uv(pos - self.bounds().unwrap().centre()) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::colour::WHITE;
+    use crate::objects::Sphere;
+
+    fn sphere(x: f32) -> ObjectRef {
+        Box::new(Sphere {
+            position: Vec3::new(x, 0.0, 0.0),
+            radius:   1.0,
+            material: Material::basic(),
+            colour:   WHITE,
+            lightmap: Default::default(),
+        })
+    }
+
+    /// Points on a grid around two overlapping spheres
+    fn points() -> impl Iterator<Item = Vec3> {
+        (0..11 * 11 * 11).map(|i| {
+            Vec3::new((i % 11) as f32, (i / 11 % 11) as f32, (i / 121) as f32) * 0.4 - Vec3::new(2.0, 2.0, 2.0)
+        })
+    }
+
+    #[test]
+    fn sharp_operations() {
+        let (a, b) = (sphere(-0.5), sphere(0.5));
+        for position in points() {
+            let (da, db) = (a.sdf(position), b.sdf(position));
+            for (operation, expected) in [
+                (Operation::Union, da.min(db)),
+                (Operation::Intersection, da.max(db)),
+                (Operation::Subtraction, da.max(-db)),
+            ] {
+                let csg = Csg::new(operation, Blend::Sharp, sphere(-0.5), sphere(0.5));
+                assert_eq!(csg.sdf(position), expected, "{:?} at {}", operation, position);
+            }
+        }
+    }
+
+    #[test]
+    fn smooth_blends() {
+        for position in points() {
+            let sharp = Csg::new(Operation::Union, Blend::Sharp, sphere(-0.5), sphere(0.5)).sdf(position);
+            for blend in [Blend::Polynomial(0.5), Blend::Exponential(0.5)] {
+                // a blend only ever adds, and never more than its reach
+                let smooth = Csg::new(Operation::Union, blend, sphere(-0.5), sphere(0.5)).sdf(position);
+                assert!(
+                    smooth <= sharp && smooth >= sharp - blend.reach() - 1e-5,
+                    "{:?} at {}",
+                    blend,
+                    position
+                );
+            }
+
+            // and as the radius goes to zero they become sharp
+            for operation in [Operation::Union, Operation::Intersection, Operation::Subtraction] {
+                let sharp = Csg::new(operation, Blend::Sharp, sphere(-0.5), sphere(0.5)).sdf(position);
+                for blend in [Blend::Polynomial(1e-4), Blend::Exponential(1e-4)] {
+                    let smooth = Csg::new(operation, blend, sphere(-0.5), sphere(0.5)).sdf(position);
+                    assert!(
+                        (smooth - sharp).abs() < 2e-4,
+                        "{:?} {:?} at {}",
+                        operation,
+                        blend,
+                        position
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn blended_bounds() {
+        let union = Csg::new(Operation::Union, Blend::Exponential(0.5), sphere(-0.5), sphere(0.5));
+        let bounds = union.bounds().unwrap();
+        assert_eq!(bounds.min, Vec3::new(-2.0, -1.5, -1.5));
+        assert_eq!(bounds.max, Vec3::new(2.0, 1.5, 1.5));
+        for position in points().filter(|&position| union.sdf(position) <= 0.0) {
+            assert!(bounds.distance(position) <= 0.0);
+        }
+    }
+}
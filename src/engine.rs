@@ -1,44 +1,249 @@
-use super::colour::{phong_ds, ACESFilm, Colour, Pixel};
-use super::objects::{EngineLight, EngineObject, PointLight};
+use super::bvh::Bvh;
+use super::camera::Camera;
+use super::colour::{phong_ds, Colour};
+use super::lights::EngineLight;
+use super::objects::EngineObject;
+use super::postprocess::PostProcess;
 use super::radiosity::{compute_direct_lighting, compute_object_radiosity, Lightmap, MAP_SIZE};
-use super::ray::Ray;
+use super::ray::{MarchResult, MarchSettings, Ray};
+use super::sampling::{Rng, Supersampling};
+use super::stats::{Stage, StageTimes};
 use super::vector::Vec3;
 
 use rayon::prelude::*;
+use std::time::{Duration, Instant};
 
-pub const WIDTH: usize = 800;
-pub const HEIGHT: usize = 600;
+pub const DEFAULT_WIDTH: usize = 800;
+pub const DEFAULT_HEIGHT: usize = 600;
 
 pub const MAX_MARCH_DISTANCE: f32 = 50.0;
 pub const SMALL_DISTANCE: f32 = 0.001;
 pub const MAX_SHAD_IT: u32 = 64;
+// times light can bounce around inside a transparent object before it's given up on
+pub const MAX_INTERNAL_REFLECTIONS: u32 = 8;
+// smallest penumbra a shadow gets, so point lights don't alias
+pub const MIN_LIGHT_SPREAD: f32 = 1.0 / 64.0;
 pub const SKY_COLOUR: Vec3 = rgb![135, 206, 235];
 
+// stop following a path once it carries less than this much of the light reaching the camera
+pub const MIN_THROUGHPUT: f32 = 0.01;
+pub const MAX_BOUNCE_DEPTH: u32 = 8;
+// time the stages of one in this many pixels, timing every ray would cost about as much as the stages themselves
+const TIMING_STRIDE: usize = 16;
+
 pub type ObjectRef = Box<dyn EngineObject>;
+pub type LightRef = Box<dyn EngineLight>;
 
 static mut N: i32 = 0;
 
 #[inline]
 fn to_pixel_range(i: f32) -> u8 { (255.0 * i).round().clamp(0.0, 255.0) as u8 }
 
-fn rot(vector: Vec3, sx: f32, cx: f32, sy: f32, cy: f32) -> Vec3 {
-    let mut x = vector.x();
-    let mut y = vector.y();
-    let mut z = vector.z();
+#[repr(align(128))]
+pub struct Aligned<T: ?Sized>(pub T);
 
-    (y, z) = (y * cy - z * sy, y * sy + z * cy);
-    (x, z) = (x * cx - z * sx, x * sx + z * cx);
-    Vec3::new(x, y, z)
+/// Resolution of the frame being rendered, the per-pixel ray directions for it, and the samples accumulated so far
+/// while the camera hasn't moved
+pub struct RenderTarget {
+    pub width:        usize,
+    pub height:       usize,
+    pub directions:   Aligned<Vec<Vec<Vec3>>>,
+    /// sum of every sample of each pixel, in linear colour
+    pub accumulation: Vec<Vec<Colour>>,
+    pub samples:      u32,
+    /// camera the accumulated samples were rendered from
+    camera:           Option<Camera>,
 }
 
-#[repr(align(128))]
-pub struct Aligned<T: ?Sized>(pub T);
+impl RenderTarget {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            directions: Aligned(vec![vec![Vec3::default(); width]; height]),
+            accumulation: vec![vec![Colour::default(); width]; height],
+            samples: 0,
+            camera: None,
+        }
+    }
+
+    pub fn resize(&mut self, width: usize, height: usize) {
+        if width != self.width || height != self.height {
+            *self = Self::new(width, height);
+        }
+    }
+
+    /// Throw away the accumulated samples, needed when anything other than the camera changes
+    pub fn reset_accumulation(&mut self) {
+        self.samples = 0;
+        self.camera = None;
+    }
+
+    /// Average of the samples so far, linear radiance before exposure and tonemapping. Rows are top first
+    pub fn linear_frame(&self) -> Vec<Vec<Colour>> {
+        let weight = (self.samples.max(1) as f32).recip();
+        self.accumulation
+            .iter()
+            .map(|row| row.iter().map(|&colour| colour * weight).collect())
+            .collect()
+    }
+
+    /// Sub-pixel offset of the next sample, from the R2 sequence so samples spread evenly over the pixel.
+    /// The first sample goes through the centre
+    fn jitter(&self) -> (f32, f32) {
+        const R2: (f32, f32) = (0.754_877_7, 0.569_840_3);
+        if self.samples == 0 {
+            return (0.0, 0.0);
+        }
+        let n = self.samples as f32;
+        ((0.5 + n * R2.0).fract() - 0.5, (0.5 + n * R2.1).fract() - 0.5)
+    }
+}
+
+/// Limits on the reflection and refraction rays followed from each sight ray
+#[derive(Clone, Copy, Debug)]
+pub struct BounceSettings {
+    /// most reflections and refractions followed from a sight ray
+    pub max_depth:        u32,
+    /// paths carrying less than this fraction of the light reaching the camera are ended
+    pub min_throughput:   f32,
+    /// instead of ending a dim path, randomly keep it and boost what it finds, so the image is unbiased on average
+    pub russian_roulette: bool,
+}
+
+impl Default for BounceSettings {
+    fn default() -> Self {
+        Self {
+            max_depth:        MAX_BOUNCE_DEPTH,
+            min_throughput:   MIN_THROUGHPUT,
+            russian_roulette: false,
+        }
+    }
+}
+
+/// What happened while rendering a frame
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RenderStats {
+    pub pixels:      usize,
+    /// deepest bounce any path reached
+    pub max_depth:   u32,
+    /// sum of the deepest bounce of every pixel's path
+    pub total_depth: u64,
+    /// wall time of each stage of the frame
+    pub times:       StageTimes,
+}
+
+impl RenderStats {
+    pub fn mean_depth(&self) -> f32 { self.total_depth as f32 / self.pixels.max(1) as f32 }
+
+    fn add_path(&mut self, path: &PathState) {
+        self.pixels += 1;
+        self.max_depth = self.max_depth.max(path.deepest);
+        self.total_depth += path.deepest as u64;
+        if let Some(times) = path.times {
+            self.times += times;
+        }
+    }
+
+    fn merge(mut self, other: Self) -> Self {
+        self.pixels += other.pixels;
+        self.max_depth = self.max_depth.max(other.max_depth);
+        self.total_depth += other.total_depth;
+        self.times += other.times;
+        self
+    }
+
+    /// The timed paths only cover some pixels, and their times are summed over every thread, so split the wall
+    /// time of tracing between the ray stages in the same proportions
+    fn share_trace_time(&mut self, trace_time: Duration) {
+        const RAY_STAGES: [Stage; 4] = [Stage::March, Stage::Shading, Stage::Shadows, Stage::Reflections];
+        let times = &mut self.times;
+        // shading was timed including the shadows and reflections it cast
+        times[Stage::Shading] = times[Stage::Shading].saturating_sub(times[Stage::Shadows] + times[Stage::Reflections]);
+
+        let timed: Duration = RAY_STAGES.iter().map(|&stage| times[stage]).sum();
+        for stage in RAY_STAGES {
+            times[stage] = match timed.is_zero() {
+                true => Duration::ZERO,
+                false => trace_time.mul_f64(times[stage].as_secs_f64() / timed.as_secs_f64()),
+            };
+        }
+    }
+}
+
+/// State carried along a sight ray and every ray spawned from it
+struct PathState<'a> {
+    march:      &'a MarchSettings,
+    /// bounces so far, and the deepest reached by any branch of the path
+    depth:      u32,
+    deepest:    u32,
+    /// fraction of the light found here that reaches the camera
+    throughput: f32,
+    rng:        Rng,
+    /// time spent in each stage of the sight ray, for the paths being timed
+    times:      Option<StageTimes>,
+}
+
+impl<'a> PathState<'a> {
+    fn new(march: &'a MarchSettings, seed: u32, timed: bool) -> Self {
+        Self {
+            march,
+            depth: 0,
+            deepest: 0,
+            throughput: 1.0,
+            rng: Rng::new(seed),
+            times: timed.then(StageTimes::default),
+        }
+    }
+
+    /// Run `f`, adding the time it takes to `stage` if the path is being timed and still on its sight ray
+    fn time<T, F: FnOnce(&mut Self) -> T>(&mut self, stage: Stage, f: F) -> T {
+        if self.times.is_none() || self.depth > 0 {
+            return f(self);
+        }
+        let start = Instant::now();
+        let result = f(self);
+        if let Some(times) = &mut self.times {
+            times[stage] += start.elapsed();
+        }
+        result
+    }
+}
 
 impl Engine {
-    pub fn render(
-        &mut self, buffer: &mut [u8], directions: &mut Aligned<Vec<Vec<Vec3>>>, mouse_x: i32, mouse_y: i32,
-        rel_move: Vec3, scroll: i32,
-    ) {
+    pub fn new(objects: Vec<ObjectRef>, camera: Camera, lights: Vec<LightRef>, sky_colour: Colour) -> Self {
+        let bvh = Bvh::new(&objects);
+        Self {
+            objects,
+            camera,
+            lights,
+            sky_colour,
+            bvh,
+            march: MarchSettings::default(),
+            bounces: BounceSettings::default(),
+            ssaa: Supersampling::default(),
+            post: PostProcess::standard(),
+        }
+    }
+
+    /// Render a frame from `self.camera` into `buffer`, a BGRA image with `pitch` bytes per row. Panics if the rows
+    /// overlap or the buffer is too short for the target's size
+    pub fn render(&self, buffer: &mut [u8], pitch: usize, target: &mut RenderTarget) -> RenderStats {
+        let (width, height) = (target.width, target.height);
+        assert!(
+            pitch >= width * 4,
+            "pitch of {} is too small for {} pixels",
+            pitch,
+            width
+        );
+        assert!(
+            height == 0 || buffer.len() >= pitch * (height - 1) + width * 4,
+            "buffer of {} bytes is too short for {}x{} pixels",
+            buffer.len(),
+            width,
+            height
+        );
+
         unsafe {
             N += 1;
             //self.camera_position.0[1] = 2.0 + 3.0 * (0.01 * N as f32).sin();
@@ -60,96 +265,243 @@ impl Engine {
         };
         */
 
-        let exposure: f32 = 1.0;
+        // start accumulating again whenever the camera moves
+        if target.camera != Some(self.camera) {
+            target.reset_accumulation();
+            target.camera = Some(self.camera);
+        }
+        let (jitter_x, jitter_y) = target.jitter();
+        let samples = target.samples;
 
-        let theta_x = -mouse_x as f32 / 600.0;
-        let theta_y = mouse_y as f32 / 600.0;
+        let (right, up, forward) = self.camera.basis();
+        let zdepth = self.camera.focal_length();
 
-        let (sx, cx) = theta_x.sin_cos();
-        let (sy, cy) = theta_y.sin_cos();
+        // let the hit distance grow with the size of a pixel, so distant surfaces don't alias
+        let mut march = self.march;
+        if march.pixel_footprint {
+            march.epsilon_slope += (height as f32 * zdepth).recip();
+        }
 
-        // move camera in direction we are facing
-        self.camera_position += rot(rel_move, sx, cx, sy, cy);
-        // start at 90 deg fov, change by 5 deg each scroll step
-        let fov_deg = 90.0 - (scroll as f32 * 5.0);
-        let zdepth = (fov_deg * 0.5).to_radians().tan().recip();
+        let mut times = StageTimes::default();
+        let start = Instant::now();
+        target
+            .directions
+            .0
+            .par_iter_mut()
+            .enumerate()
+            .for_each(|(y_inv, rows)| {
+                for x in 0..width {
+                    let y = height - y_inv;
+                    // divide u by height to account for aspect ratio
+                    let u = 2.0 * (x as f32 + jitter_x - (0.5 * (width as f32))) / height as f32;
+                    let v = 2.0 * (y as f32 + jitter_y - (0.5 * (height as f32))) / height as f32;
+
+                    // array of vectors out of each pixel, normalised when they're used
+                    rows[x] = right * u + up * v + forward * zdepth;
+                }
+            });
+
+        times[Stage::Directions] = start.elapsed();
+
+        let directions = &target.directions.0;
+        let seed_base = samples.wrapping_mul((width * height) as u32);
+        let timed = |x: usize, y_inv: usize| (x + y_inv).is_multiple_of(TIMING_STRIDE);
+        let trace = |seed: u32, direction: Vec3, timed: bool, stats: &mut RenderStats| {
+            let mut path = PathState::new(&march, seed, timed);
+            let colour = self.cast_sight_ray(&mut path, self.camera.position, direction.normalized());
+            stats.add_path(&path);
+            colour
+        };
 
-        directions.0.par_iter_mut().enumerate().for_each(|(y_inv, rows)| {
-            for x in 0..WIDTH {
-                let y = HEIGHT - y_inv;
-                let u = 2.0 * (x as f32 - (0.5 * (WIDTH as f32))) / HEIGHT as f32; // divide u by height to account for aspect ratio
-                let v = 2.0 * (y as f32 - (0.5 * (HEIGHT as f32))) / HEIGHT as f32;
+        // linear colour of every pixel in this frame
+        let mut frame = vec![Vec::with_capacity(width); height];
+        let start = Instant::now();
+        let (mut stats, trace_time) = if !self.ssaa.enabled() {
+            let stats = frame
+                .par_iter_mut()
+                .enumerate()
+                .map(|(y_inv, rows)| {
+                    let mut stats = RenderStats::default();
+                    for (x, &direction) in directions[y_inv].iter().enumerate() {
+                        let seed = ((y_inv * width + x) as u32).wrapping_add(seed_base);
+                        rows.push(trace(seed, direction, timed(x, y_inv), &mut stats));
+                    }
+                    stats
+                })
+                .reduce(RenderStats::default, RenderStats::merge);
+            (stats, start.elapsed())
+        } else {
+            let ssaa = self.ssaa;
+            let count = ssaa.sample_count();
+            // image plane distance between pixels
+            let pixel_size = 2.0 / height as f32;
+
+            // every sample of every pixel, with where in the pixel it was taken
+            let mut pixel_samples = vec![Vec::with_capacity(width * count); height];
+            let stats = pixel_samples
+                .par_iter_mut()
+                .enumerate()
+                .map(|(y_inv, row)| {
+                    let mut stats = RenderStats::default();
+                    let mut offsets = Vec::with_capacity(count);
+                    for (x, &centre) in directions[y_inv].iter().enumerate() {
+                        let pixel_seed =
+                            (((y_inv * width + x) * count) as u32).wrapping_add(seed_base.wrapping_mul(count as u32));
+                        let mut rng = Rng::new(!pixel_seed);
+                        ssaa.offsets(&mut rng, &mut offsets);
+
+                        for (i, &(dx, dy)) in offsets.iter().enumerate() {
+                            let direction = centre + (right * dx - up * dy) * pixel_size;
+                            let seed = pixel_seed.wrapping_add(i as u32);
+                            row.push((dx, dy, trace(seed, direction, timed(x, y_inv), &mut stats)));
+                        }
+                    }
+                    stats
+                })
+                .reduce(RenderStats::default, RenderStats::merge);
+            let trace_time = start.elapsed();
+
+            // filter the samples of this pixel and its neighbours back into the pixel
+            let radius = ssaa.filter.radius();
+            let reach = (radius - 0.5).ceil() as usize;
+            frame.par_iter_mut().enumerate().for_each(|(y_inv, rows)| {
+                for x in 0..width {
+                    let mut sum = Colour::default();
+                    let mut total_weight = 0.0;
+                    let first = y_inv.saturating_sub(reach);
+                    for (ny, sample_row) in pixel_samples.iter().enumerate().take(y_inv + reach + 1).skip(first) {
+                        for nx in x.saturating_sub(reach)..(x + reach + 1).min(width) {
+                            for &(dx, dy, colour) in &sample_row[nx * count..(nx + 1) * count] {
+                                let weight = ssaa
+                                    .filter
+                                    .weight(nx as f32 - x as f32 + dx, ny as f32 - y_inv as f32 + dy);
+                                sum += colour * weight;
+                                total_weight += weight;
+                            }
+                        }
+                    }
+                    // negative lobes can ring below zero next to bright edges
+                    rows.push(if total_weight > 0.0 {
+                        (sum / total_weight).max(Colour::default())
+                    } else {
+                        Colour::default()
+                    });
+                }
+            });
+            (stats, trace_time)
+        };
 
-                // array of vectors out of each pixel
-                rows[x] = rot(Vec3::new(u, v, zdepth), sx, cx, sy, cy).normalized();
-            }
-        });
+        let mut colours = vec![Vec::with_capacity(width); height];
+        let sample_weight = ((samples + 1) as f32).recip();
+
+        colours
+            .par_iter_mut()
+            .zip(target.accumulation.par_iter_mut())
+            .enumerate()
+            .for_each(|(y_inv, (rows, accumulated_row))| {
+                for (x, accumulated) in accumulated_row.iter_mut().enumerate() {
+                    let colour = frame[y_inv][x];
+                    // the first sample replaces whatever was accumulated before the last reset
+                    *accumulated = if samples == 0 { colour } else { *accumulated + colour };
 
-        let buffer_pixels =
-            unsafe { std::slice::from_raw_parts_mut(buffer.as_mut_ptr() as *mut Pixel, WIDTH * HEIGHT) };
-
-        let mut colours = vec![Vec::with_capacity(WIDTH); HEIGHT];
-
-        colours.par_iter_mut().enumerate().for_each(|(y_inv, rows)| {
-            for x in 0..WIDTH {
-                /*
-                let mut ssaa_colours: [Vec3; 4] = [Default::default(); 4];
-                let offsets: [Vec3; 4] = [
-                    Vec3::new(0.5 / WIDTH as f32, 0.0 / HEIGHT as f32, 0.0),
-                    Vec3::new(-0.5 / WIDTH as f32, 0.0 / HEIGHT as f32, 0.0),
-                    Vec3::new(0.0 / WIDTH as f32, 0.5 / HEIGHT as f32, 0.0),
-                    Vec3::new(0.0 / WIDTH as f32, -0.5 / HEIGHT as f32, 0.0),
-                ];
-                for i in 0..4 {
-                    ssaa_colours[i] =
-                        self.cast_sight_ray(self.camera_position, (directions.0[y_inv][x] + offsets[i]).normalized());
+                    rows.push(*accumulated * sample_weight);
                 }
-                let colour_linear = (ssaa_colours[0] + ssaa_colours[1] + ssaa_colours[2] + ssaa_colours[3]) / 4.0;
-                */
-                let colour_linear: Colour = self.cast_sight_ray(self.camera_position, directions.0[y_inv][x]);
-                let colour_srgb = ACESFilm(colour_linear * exposure).sqrt();
+            });
+        target.samples += 1;
 
-                rows.push(colour_srgb);
-            }
-        });
+        times[Stage::Resolve] = start.elapsed() - trace_time;
 
-        buffer_pixels.par_iter_mut().enumerate().for_each(|(i, pixel)| {
-            let x = i % WIDTH;
-            let y_inv = i / WIDTH;
-            let colour_srgb = colours[y_inv][x];
-            *pixel = Pixel {
-                r: to_pixel_range(colour_srgb.z()),
-                g: to_pixel_range(colour_srgb.y()),
-                b: to_pixel_range(colour_srgb.x()),
-                a: 0,
-            };
-        });
+        // linear HDR in, display values out
+        let start = Instant::now();
+        self.post.apply(&mut colours);
+        times[Stage::Post] = start.elapsed();
+
+        let start = Instant::now();
+        buffer
+            .par_chunks_mut(pitch)
+            .take(height)
+            .enumerate()
+            .for_each(|(y_inv, row)| {
+                for (x, pixel) in row[..width * 4].chunks_exact_mut(4).enumerate() {
+                    let colour_srgb = colours[y_inv][x];
+                    pixel.copy_from_slice(&[
+                        to_pixel_range(colour_srgb.z()),
+                        to_pixel_range(colour_srgb.y()),
+                        to_pixel_range(colour_srgb.x()),
+                        0,
+                    ]);
+                }
+            });
+        times[Stage::Blit] = start.elapsed();
+
+        stats.share_trace_time(trace_time);
+        stats.times += times;
+        stats
     }
 
-    fn cast_sight_ray(&self, position: Vec3, direction: Vec3) -> Colour {
+    fn cast_sight_ray(&self, path: &mut PathState, position: Vec3, direction: Vec3) -> Colour {
         let colour: Colour;
 
         let mut ray = Ray { position, direction };
         // object the sight ray hit
-        let has_hit = ray.march(&self.objects, None);
+        let has_hit = path.time(Stage::March, |path| {
+            ray.march(&self.objects, &self.bvh, path.march, None)
+        });
 
         // if we hit an object, colour this pixel
         //sky_colour[1] = sky_colour[1] * (direction.y.max(0.2));
         //sky_colour[0] = sky_colour[0] * (direction.y.max(0.2));
 
+        // running out of steps almost always means grazing an object, so shade that rather than leave a hole
         match has_hit {
-            None => colour = SKY_COLOUR,
-            Some(obj_index) => colour = self.shade_object(obj_index, ray.position, direction),
+            MarchResult::Miss => colour = self.sky_colour,
+            MarchResult::Hit(obj_index) | MarchResult::OutOfSteps(obj_index) => {
+                colour = path.time(Stage::Shading, |path| {
+                    self.shade_object(path, obj_index, ray.position, direction)
+                })
+            }
         }
         colour
     }
 
-    fn shade_object(&self, obj_index: usize, position: Vec3, direction: Vec3) -> Colour {
+    /// Follow a reflection or refraction from the current path, with `weight` the fraction of its light that reaches
+    /// the path. Ends the path once it's too deep or too dim
+    fn follow<F: FnOnce(&mut PathState) -> Colour>(&self, path: &mut PathState, weight: f32, cast: F) -> Colour {
+        if path.depth >= self.bounces.max_depth {
+            return Colour::default();
+        }
+
+        let throughput = path.throughput * weight;
+        let mut boost = 1.0;
+        if throughput < self.bounces.min_throughput {
+            if !self.bounces.russian_roulette {
+                return Colour::default();
+            }
+            let survival = throughput / self.bounces.min_throughput;
+            if path.rng.next_f32() >= survival {
+                return Colour::default();
+            }
+            boost = survival.recip();
+        }
+
+        let (depth, old_throughput) = (path.depth, path.throughput);
+        path.depth += 1;
+        path.deepest = path.deepest.max(path.depth);
+        path.throughput = throughput * boost;
+
+        let colour = cast(path);
+
+        path.depth = depth;
+        path.throughput = old_throughput;
+        colour * boost
+    }
+
+    fn shade_object(&self, path: &mut PathState, obj_index: usize, position: Vec3, direction: Vec3) -> Colour {
         let mut final_colour: Colour;
         let object = &self.objects[obj_index];
 
         let object_colour = object.colour(position);
-        let object_mat = object.material();
+        let object_mat = object.material_at(position);
         let n = object.calculate_normal(position); // normal vector
 
         let mut ambient: Colour;
@@ -169,81 +521,130 @@ impl Engine {
 
         //let ambient = object_colour * object_mat.ambient;
 
-        // get normalised vector to light, and distance
-        let vector_to_light = self.light.get_position() - position;
-        let distance_to_light = vector_to_light.mag();
-        let vector_to_light = vector_to_light / distance_to_light;
-
-        // get the diffuse and specular lighting of this object
-        let (diffuse, specular) = phong_ds(
-            n,
-            vector_to_light,
-            distance_to_light,
-            self.light.get_intensity(),
-            object_mat,
-            direction,
-        );
+        // sum up the direct lighting from every light
+        let mut direct = Colour::default();
+        let mut highlights = Colour::default();
+        for light in &self.lights {
+            // get normalised vector to light, distance, and intensity at this point
+            let light_sample = light.sample(position);
+
+            // get the diffuse and specular lighting of this object
+            let (diffuse, specular) = phong_ds(
+                n,
+                light_sample.direction,
+                light_sample.intensity,
+                &object_mat,
+                direction,
+            );
+            // facing away from the light, so no need to check for shadow
+            if (diffuse + specular).mag_sqd() <= 0.0 {
+                continue;
+            }
 
-        // cast a shadow ray to see if this point is blocked by another object
-        let shade = Ray {
-            position,
-            direction: vector_to_light,
+            // cast a shadow ray to see if this point is blocked by another object
+            let shade = path.time(Stage::Shadows, |_| {
+                Ray {
+                    position,
+                    direction: light_sample.direction,
+                }
+                .smooth_shadow_march(
+                    &self.objects,
+                    &self.bvh,
+                    obj_index,
+                    light_sample.distance,
+                    light_sample.spread,
+                )
+            });
+
+            direct += shade * diffuse;
+            highlights += shade * specular;
         }
-        .smooth_shadow_march(&self.objects, obj_index, distance_to_light, 16.0);
 
-        final_colour = ambient + object_colour * (shade * (diffuse + specular));
+        final_colour = ambient + object_colour.element_mul(direct);
+        let highlights = object_colour.element_mul(highlights);
+
+        // very cheap fresnel effect
+        let mut reflectance = (1.0 - n.dot(-direction)).clamp(0.0, 1.0).powi(5);
+
+        // transmissive objects let some light through, split between reflection and refraction by the fresnel equations
+        if object_mat.transmission > 1e-3 {
+            let fresnel = object_mat.fresnel(n.dot(-direction));
+            let weight = object_mat.transmission * (1.0 - fresnel) * object_colour.max_element();
+            let transmitted = path.time(Stage::Reflections, |path| {
+                self.follow(path, weight, |path| {
+                    self.cast_refraction_ray(path, obj_index, position, direction, n)
+                })
+            });
+
+            // light passing through replaces the diffuse surface, but the highlights stay
+            final_colour = final_colour * (1.0 - object_mat.transmission)
+                + transmitted.element_mul(object_colour) * (object_mat.transmission * (1.0 - fresnel));
+            reflectance = object_mat.transmission * fresnel;
+        }
+        final_colour += highlights;
 
-        // if object_mat.refract
-        /*
-        if object_mat.reflectivity > 1e-3 {
-            let ior = 2.5;
-            let refraction_vector = direction.refract(n, 1.0 / ior);
-            let mut enter_ray = Ray {
-                position:  position + refraction_vector * (3.0 * SMALL_DISTANCE),
-                direction: refraction_vector,
-            };
+        // if the object is reflective, cast a reflection ray
+        let reflectance = (reflectance + object_mat.reflectivity).clamp(0.0, 1.0);
+        if object_mat.reflectivity > 1e-3 || object_mat.transmission > 1e-3 {
+            let reflection_vector = direction.reflect(n);
+            let reflection_colour = path.time(Stage::Reflections, |path| {
+                self.follow(path, reflectance * object_colour.max_element(), |path| {
+                    self.cast_sight_ray(
+                        path,
+                        position + (reflection_vector * 3.0 * SMALL_DISTANCE),
+                        reflection_vector,
+                    )
+                })
+            });
+
+            final_colour += reflectance * reflection_colour.element_mul(object_colour);
+        }
 
-            enter_ray.internal_march(object);
+        final_colour
+    }
 
-            let exit_n = object.calculate_normal(enter_ray.position);
-            let mut exit_direction = refraction_vector.refract(-exit_n, ior);
-            let exit_pos = enter_ray.position;
+    /// Follow light refracted into an object at `position` until it leaves, returning the colour it brings back.
+    /// Light that hits the inside of the surface too steeply is totally internally reflected, and colour is absorbed
+    /// along the way
+    fn cast_refraction_ray(
+        &self, path: &mut PathState, obj_index: usize, position: Vec3, direction: Vec3, n: Vec3,
+    ) -> Colour {
+        let object = &self.objects[obj_index];
+        let object_mat = object.material_at(position);
 
-            // TIR
-            if exit_direction.mag_sqd() == 0.0 {
-                exit_direction = refraction_vector.reflect(-exit_n);
-            }
+        // start just inside the surface, the hit can be a little way outside it
+        let mut ray = Ray {
+            position:  position - n * (object.sdf(position).max(0.0) + 2.0 * SMALL_DISTANCE),
+            direction: direction.refract(n, object_mat.ior.recip()),
+        };
+        let mut distance_inside = 0.0;
 
-            let mut exit_ray = Ray {
-                position:  exit_pos,
-                direction: exit_direction,
-            };
+        for _ in 0..MAX_INTERNAL_REFLECTIONS {
+            distance_inside += ray.internal_march(object, path.march);
 
-            let refr_hit = exit_ray.march(&self.objects, Some(obj_index));
+            let exit_n = object.calculate_normal(ray.position);
+            let exit_direction = ray.direction.refract(-exit_n, object_mat.ior);
 
-            if refr_hit.is_some() {
-                final_colour += self.shade_object(refr_hit.unwrap(), exit_ray.position, exit_ray.direction);
+            // total internal reflection
+            if exit_direction.mag_sqd() == 0.0 {
+                ray.direction = ray.direction.reflect(-exit_n);
+                ray.position -= exit_n * (2.0 * SMALL_DISTANCE);
+                continue;
             }
-        }
-        */
 
-        // if the object is reflective, cast a reflection ray
-        if object_mat.reflectivity > 1e-3 {
-            // very cheap fresnel effect
-            let fresnel = (1.0 - n.dot(-direction)).clamp(0.0, 1.0).powi(5);
-
-            let reflection_vector = direction.reflect(n);
-            let reflection_colour =
-                self.cast_sight_ray(position + (reflection_vector * 3.0 * SMALL_DISTANCE), reflection_vector);
-
-            final_colour +=
-                (fresnel + object_mat.reflectivity).clamp(0.0, 1.0) * reflection_colour.element_mul(object_colour);
+            let exit_colour = self.cast_sight_ray(path, ray.position + exit_n * (3.0 * SMALL_DISTANCE), exit_direction);
+            return exit_colour.element_mul(object_mat.transmittance(distance_inside));
         }
 
-        final_colour
+        // trapped inside, so all of it is absorbed
+        Colour::default()
     }
 
+    /// Rebuild the bounding volume hierarchy, needed whenever objects are added, removed or moved
+    pub fn update_bvh(&mut self) { self.bvh = Bvh::new(&self.objects); }
+
     pub fn compute_lightmaps(&mut self) {
+        self.update_bvh();
         self.objects.iter_mut().for_each(|x| x.clear_lightmap());
 
         // get all objects with a lightmap
@@ -280,8 +681,15 @@ impl Engine {
             }
         }
 
-        let (mut emissive_maps, new_lightmaps) =
-            compute_direct_lighting(&self.light, &obj_indexes, &self.objects, &point_cloud, &colour_cloud);
+        let (mut emissive_maps, new_lightmaps) = compute_direct_lighting(
+            &self.lights,
+            &obj_indexes,
+            &self.objects,
+            &self.bvh,
+            &self.march,
+            &point_cloud,
+            &colour_cloud,
+        );
 
         for (cloud_index, &obj_index) in obj_indexes.iter().enumerate() {
             self.objects[obj_index].set_lightmap(new_lightmaps[cloud_index]);
@@ -305,6 +713,8 @@ impl Engine {
 
                 let (lit_lightmap, new_emissive_map) = compute_object_radiosity(
                     &self.objects,
+                    &self.bvh,
+                    &self.march,
                     object,
                     obj_cloud_index,
                     obj_eng_index,
@@ -327,10 +737,70 @@ impl Engine {
 }
 
 pub struct Engine {
-    pub objects:         Vec<ObjectRef>,
-    pub camera_position: Vec3,
-    pub light:           PointLight,
+    pub objects:    Vec<ObjectRef>,
+    pub camera:     Camera,
+    pub lights:     Vec<LightRef>,
+    pub sky_colour: Colour,
+    pub bvh:        Bvh,
+    pub march:      MarchSettings,
+    pub bounces:    BounceSettings,
+    pub ssaa:       Supersampling,
+    pub post:       PostProcess,
 }
 
 unsafe impl Sync for Engine {}
 unsafe impl Send for Engine {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scene::parse_scene;
+    use std::path::Path;
+
+    #[test]
+    fn jitter_covers_pixel() {
+        let mut target = RenderTarget::new(1, 1);
+        assert_eq!(target.jitter(), (0.0, 0.0));
+
+        let mut offsets = Vec::new();
+        for samples in 1..64 {
+            target.samples = samples;
+            let (x, y) = target.jitter();
+            assert!((-0.5..0.5).contains(&x) && (-0.5..0.5).contains(&y));
+            offsets.push((x, y));
+        }
+        // spread evenly, so every quarter of the pixel gets a fair share
+        for (right, up) in [(false, false), (false, true), (true, false), (true, true)] {
+            let quarter = offsets
+                .iter()
+                .filter(|&&(x, y)| (x >= 0.0) == right && (y >= 0.0) == up)
+                .count();
+            assert!((12..=20).contains(&quarter), "{} samples in one quarter", quarter);
+        }
+    }
+
+    #[test]
+    fn accumulation_restarts_when_the_camera_moves() {
+        // nothing but sky, so every sample is the same
+        let mut engine = parse_scene("sky 255 128 0", Path::new("")).unwrap();
+        let (width, height) = (8, 6);
+        let mut target = RenderTarget::new(width, height);
+        let mut buffer = vec![0; width * height * 4];
+
+        engine.render(&mut buffer, width * 4, &mut target);
+        engine.render(&mut buffer, width * 4, &mut target);
+        assert_eq!(target.samples, 2);
+        // the linear frame is the average of the samples
+        let linear = target.linear_frame();
+        assert_eq!((linear.len(), linear[0].len()), (height, width));
+        assert_eq!(linear[3][4], target.accumulation[3][4] * 0.5);
+        assert_eq!(linear[3][4], engine.sky_colour);
+
+        engine.camera.yaw += 0.1;
+        engine.render(&mut buffer, width * 4, &mut target);
+        assert_eq!(target.samples, 1);
+
+        target.reset_accumulation();
+        assert_eq!(target.samples, 0);
+    }
+}
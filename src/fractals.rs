@@ -0,0 +1,328 @@
+//! Fractals. Each one is worked out in its own space, about the origin, and scaled up by `size` into the world. Their
+//! distances come from iterating a point, and the Mandelbulb and Mandelbox ones are only estimates, so they're harder
+//! on the march and normals than any other object.
+//!
+//! While iterating they also keep an orbit trap, how close the point's orbit came to the origin, or for the sponge to
+//! the middle of a cell. Surfaces are coloured with `trap_colour` where the orbit came close, fading to `colour` where
+//! it stayed away.
+
+use crate::bvh::Aabb;
+use crate::colour::Colour;
+use crate::material::Material;
+use crate::objects::{globe_direction, globe_uv, outer_surface_point, EngineObject};
+use crate::radiosity::Lightmap;
+use crate::vector::Vec3;
+
+/// Mandelbox sphere fold radii, squared. inside the minimum radius points are scaled up by a fixed amount, between the
+/// two they're inverted through the fixed radius
+const MIN_RADIUS_SQD: f32 = 0.25;
+const FIXED_RADIUS_SQD: f32 = 1.0;
+
+const SQRT_3: f32 = 1.732_050_8;
+
+/// The 3D Mandelbulb, `power` is the exponent. 8 is the classic bulb, which fits inside a radius of about 1.1, lower
+/// powers reach further out, up to 2 for a power of 2
+#[derive(Clone, Copy)]
+pub struct Mandelbulb {
+    pub position:    Vec3,
+    /// world size of one unit of the fractal's own space
+    pub size:        f32,
+    pub iterations:  u32,
+    /// orbits that get this far from the origin have escaped
+    pub bailout:     f32,
+    pub power:       f32,
+    pub material:    Material,
+    pub colour:      Colour,
+    pub trap_colour: Colour,
+    pub lightmap:    Lightmap,
+}
+
+/// Menger sponge filling the cube from -1 to 1, with `power` the scale of each level. 3 is the classic sponge
+#[derive(Clone, Copy)]
+pub struct MengerSponge {
+    pub position:    Vec3,
+    pub size:        f32,
+    pub iterations:  u32,
+    /// stop cutting holes once further than this many cells from the surface
+    pub bailout:     f32,
+    pub power:       f32,
+    pub material:    Material,
+    pub colour:      Colour,
+    pub trap_colour: Colour,
+    pub lightmap:    Lightmap,
+}
+
+/// Sierpinski tetrahedron with corners at (1, 1, 1), (-1, -1, 1), (1, -1, -1) and (-1, 1, -1), with `power` the scale
+/// of each level. 2 is the classic one, more leaves gaps between the smaller copies
+#[derive(Clone, Copy)]
+pub struct SierpinskiTetrahedron {
+    pub position:    Vec3,
+    pub size:        f32,
+    pub iterations:  u32,
+    pub bailout:     f32,
+    pub power:       f32,
+    pub material:    Material,
+    pub colour:      Colour,
+    pub trap_colour: Colour,
+    pub lightmap:    Lightmap,
+}
+
+/// Mandelbox, `power` is the scale after each fold, which must be more than 1 or less than -1. 2 fills the cube from -6
+/// to 6, -1.5 is the other common choice
+#[derive(Clone, Copy)]
+pub struct Mandelbox {
+    pub position:    Vec3,
+    pub size:        f32,
+    pub iterations:  u32,
+    pub bailout:     f32,
+    pub power:       f32,
+    pub material:    Material,
+    pub colour:      Colour,
+    pub trap_colour: Colour,
+    pub lightmap:    Lightmap,
+}
+
+// each fractal has an `orbit`, giving the distance and the orbit trap from 0 to 1 at a point in its own space, and an
+// `extent`, half the width of the cube it fits in
+macro_rules! fractal_funcs {
+    () => {
+        fn sdf(&self, position: Vec3) -> f32 { self.orbit((position - self.position) / self.size).0 * self.size }
+        fn bounds(&self) -> Option<Aabb> { Some(Aabb::around(self.position, self.extent() * self.size)) }
+
+        fn colour(&self, position: Vec3) -> Colour {
+            let trap = self.orbit((position - self.position) / self.size).1;
+            self.trap_colour * (1.0 - trap) + self.colour * trap
+        }
+        fn material(&self) -> &Material { &self.material }
+
+        fn radiosity_collide(&self) -> bool { true }
+
+        // wrapped around like a globe, marching in from the bounds as the surface is full of holes
+        fn get_lightmap(&self) -> Option<&Lightmap> { Some(&self.lightmap) }
+        fn set_lightmap(&mut self, new_lightmap: Lightmap) { self.lightmap = new_lightmap; }
+        fn clear_lightmap(&mut self) { self.lightmap = Lightmap::default() }
+
+        fn get_sample_pos(&self, u: usize, v: usize) -> Vec3 {
+            outer_surface_point(self, self.bounds().unwrap(), globe_direction(u, v))
+        }
+        fn sample_uv_from_pos(&self, pos: Vec3) -> (f32, f32) { globe_uv(pos - self.position) }
+    };
+}
+
+/// Distance to the cube from -1 to 1
+fn unit_cube(p: Vec3) -> f32 {
+    let q = p.abs() - Vec3::new(1.0, 1.0, 1.0);
+    q.max(Vec3::default()).mag() + q.max_element().min(0.0)
+}
+
+/// Distance to the tetrahedron `SierpinskiTetrahedron` is built from, a bound rather than exact near its edges
+fn unit_tetrahedron(p: Vec3) -> f32 {
+    let (x, y, z) = (p.x(), p.y(), p.z());
+    let face = (-x - y - z).max(x + y - z).max(-x + y + z).max(x - y + z);
+    (face - 1.0) / SQRT_3
+}
+
+impl Mandelbulb {
+    // orbits escape once past 2^(1 / (power - 1)), and points past the bailout are never iterated. a little extra as
+    // the distance estimate isn't exact
+    fn extent(&self) -> f32 { 2f32.powf((self.power - 1.0).recip()).min(self.bailout.max(1.0)) * 1.1 }
+
+    fn orbit(&self, p: Vec3) -> (f32, f32) {
+        let mut z = p;
+        let mut r = z.mag();
+        // how fast the orbit grows with p, for the distance estimate
+        let mut dr = 1.0;
+        let mut trap = f32::INFINITY;
+
+        for _ in 0..self.iterations {
+            if r > self.bailout || r == 0.0 {
+                break;
+            }
+            // raise to the power in spherical coordinates, with y up
+            let theta = (z.y() / r).clamp(-1.0, 1.0).acos() * self.power;
+            let phi = z.z().atan2(z.x()) * self.power;
+            dr = r.powf(self.power - 1.0) * self.power * dr + 1.0;
+
+            let spherical = Vec3::new(theta.sin() * phi.cos(), theta.cos(), theta.sin() * phi.sin());
+            z = spherical * r.powf(self.power) + p;
+            r = z.mag();
+            trap = trap.min(r * r);
+        }
+        if r == 0.0 {
+            return (0.0, 0.0);
+        }
+        (0.5 * r.ln() * r / dr, trap.min(1.0))
+    }
+}
+
+impl MengerSponge {
+    fn extent(&self) -> f32 { 1.0 }
+
+    fn orbit(&self, p: Vec3) -> (f32, f32) {
+        let mut distance = unit_cube(p);
+        // cells per unit at this level
+        let mut scale = 1.0;
+        let mut trap = f32::INFINITY;
+
+        for _ in 0..self.iterations {
+            // finer holes only ever add distance, so far from the surface they aren't worth cutting
+            if distance > self.bailout / scale {
+                break;
+            }
+            // where p is in its cell at this level, from 0 in the middle to 1 at the sides
+            let cell = |x: f32| ((x * scale + 1.0).rem_euclid(2.0) - 1.0).abs();
+            let (x, y, z) = (cell(p.x()), cell(p.y()), cell(p.z()));
+
+            // cut the cross of square holes through the middle of every cell
+            let cross = x.max(y).min(y.max(z)).min(z.max(x));
+            distance = distance.max((1.0 / self.power - cross) / scale);
+            scale *= self.power;
+            trap = trap.min((x * x + y * y + z * z).sqrt());
+        }
+        (distance, (trap / SQRT_3).min(1.0))
+    }
+}
+
+impl SierpinskiTetrahedron {
+    fn extent(&self) -> f32 { 1.0 }
+
+    fn orbit(&self, p: Vec3) -> (f32, f32) {
+        let k = self.power;
+        let (mut x, mut y, mut z) = (p.x(), p.y(), p.z());
+        let mut scale = 1.0;
+        let mut trap = f32::INFINITY;
+
+        for _ in 0..self.iterations {
+            // reflect every corner onto (1, 1, 1), then scale the copy there up to fill the whole
+            if x + y < 0.0 {
+                (x, y) = (-y, -x);
+            }
+            if x + z < 0.0 {
+                (x, z) = (-z, -x);
+            }
+            if y + z < 0.0 {
+                (y, z) = (-z, -y);
+            }
+            x = k * x - (k - 1.0);
+            y = k * y - (k - 1.0);
+            z = k * z - (k - 1.0);
+            scale *= k;
+
+            let r = (x * x + y * y + z * z).sqrt();
+            trap = trap.min(r);
+            if r > self.bailout {
+                break;
+            }
+        }
+        (unit_tetrahedron(Vec3::new(x, y, z)) / scale, (trap / SQRT_3).min(1.0))
+    }
+}
+
+impl Mandelbox {
+    // nothing with a coordinate past this stays bounded
+    fn extent(&self) -> f32 { 2.0 * (self.power.abs() + 1.0) / (self.power.abs() - 1.0) }
+
+    fn orbit(&self, p: Vec3) -> (f32, f32) {
+        let one = Vec3::new(1.0, 1.0, 1.0);
+        let mut z = p;
+        let mut dr = 1.0;
+        let mut trap = f32::INFINITY;
+
+        for _ in 0..self.iterations {
+            // box fold, reflecting anything outside the cube from -1 to 1 back in
+            z = z.max(-one).min(one) * 2.0 - z;
+
+            // sphere fold
+            let r_sqd = z.mag_sqd();
+            let fold = if r_sqd < MIN_RADIUS_SQD {
+                FIXED_RADIUS_SQD / MIN_RADIUS_SQD
+            } else if r_sqd < FIXED_RADIUS_SQD {
+                FIXED_RADIUS_SQD / r_sqd
+            } else {
+                1.0
+            };
+            z = z * (fold * self.power) + p;
+            dr = dr * fold * self.power.abs() + 1.0;
+
+            let r = z.mag();
+            trap = trap.min(r);
+            if r > self.bailout {
+                break;
+            }
+        }
+        (z.mag() / dr, (trap * 0.5).min(1.0))
+    }
+}
+
+impl EngineObject for Mandelbulb {
+    fractal_funcs!();
+}
+
+impl EngineObject for MengerSponge {
+    fractal_funcs!();
+}
+
+impl EngineObject for SierpinskiTetrahedron {
+    fractal_funcs!();
+}
+
+impl EngineObject for Mandelbox {
+    fractal_funcs!();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::colour::WHITE;
+
+    macro_rules! fractal {
+        ($fractal:ident, $iterations:expr, $bailout:expr, $power:expr) => {
+            Box::new($fractal {
+                position:    Vec3::new(1.0, -2.0, 0.5),
+                size:        1.5,
+                iterations:  $iterations,
+                bailout:     $bailout,
+                power:       $power,
+                material:    Material::basic(),
+                colour:      WHITE,
+                trap_colour: WHITE,
+                lightmap:    Default::default(),
+            })
+        };
+    }
+
+    #[test]
+    fn nothing_outside_bounds() {
+        let fractals: Vec<Box<dyn EngineObject>> = vec![
+            fractal!(Mandelbulb, 8, 2.0, 8.0),
+            fractal!(Mandelbulb, 8, 4.0, 3.0),
+            fractal!(Mandelbulb, 8, 4.0, 2.0),
+            fractal!(Mandelbulb, 20, 100.0, 2.0),
+            fractal!(MengerSponge, 5, 1.0, 3.0),
+            fractal!(SierpinskiTetrahedron, 10, 4.0, 2.0),
+            fractal!(Mandelbox, 12, 16.0, 2.0),
+            fractal!(Mandelbox, 12, 16.0, -1.5),
+        ];
+
+        const STEPS: usize = 16;
+        for fractal in fractals {
+            let bounds = fractal.bounds().unwrap();
+            let (centre, half_size) = (bounds.centre(), (bounds.max - bounds.min) * 0.5);
+            // every face of the bounds, just outside them and then further out
+            for i in 0..(STEPS + 1).pow(2) * 6 {
+                let t = |n: usize| (n % (STEPS + 1)) as f32 / STEPS as f32 * 2.0 - 1.0;
+                let (u, v, face) = (t(i), t(i / (STEPS + 1)), i / (STEPS + 1).pow(2));
+                let side = if face % 2 == 0 { 1.0 } else { -1.0 };
+                let offset = match face / 2 {
+                    0 => Vec3::new(side, u, v),
+                    1 => Vec3::new(u, side, v),
+                    _ => Vec3::new(u, v, side),
+                };
+                for &scale in &[1.01, 1.5, 3.0] {
+                    let position = centre + offset.element_mul(half_size) * scale;
+                    assert!(fractal.sdf(position) > 0.0, "{} is past the bounds", position);
+                }
+            }
+        }
+    }
+}
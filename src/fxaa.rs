@@ -0,0 +1,151 @@
+//! Fast approximate anti-aliasing. Blurs along edges found from the luma of neighbouring pixels, so it only costs a
+//! few lookups per pixel. Runs as a post-process pass after the output transfer, on the values sent to the display.
+
+use super::colour::Colour;
+use super::postprocess::{Frame, PostPass};
+use super::vector::Vec3;
+
+use rayon::prelude::*;
+
+pub const FXAA_SPAN_MAX: f32 = 8.0;
+pub const FXAA_REDUCE_MUL: f32 = 1.0 / 8.0;
+pub const FXAA_REDUCE_MIN: f32 = 1.0 / 128.0;
+
+const LUMA: Vec3 = Vec3::new(0.299, 0.587, 0.114);
+
+#[derive(Clone, Copy, Debug)]
+pub struct Fxaa {
+    /// furthest the blur reaches along an edge, in pixels
+    pub span_max:   f32,
+    /// how much the average luma reduces the blur, so bright areas aren't smeared as much
+    pub reduce_mul: f32,
+    /// least the blur is reduced by, stops it blowing up in dark areas
+    pub reduce_min: f32,
+}
+
+impl Default for Fxaa {
+    fn default() -> Self {
+        Self {
+            span_max:   FXAA_SPAN_MAX,
+            reduce_mul: FXAA_REDUCE_MUL,
+            reduce_min: FXAA_REDUCE_MIN,
+        }
+    }
+}
+
+/// Bilinearly filtered lookup at a position in pixels, clamped to the edge of the image
+fn sample(colours: &[Vec<Colour>], x: f32, y: f32) -> Colour {
+    let (width, height) = (colours[0].len(), colours.len());
+    let x = x.clamp(0.0, (width - 1) as f32);
+    let y = y.clamp(0.0, (height - 1) as f32);
+
+    let (x0, y0) = (x.floor() as usize, y.floor() as usize);
+    let (x1, y1) = ((x0 + 1).min(width - 1), (y0 + 1).min(height - 1));
+    let (tx, ty) = (x - x0 as f32, y - y0 as f32);
+
+    let top = colours[y0][x0] * (1.0 - tx) + colours[y0][x1] * tx;
+    let bottom = colours[y1][x0] * (1.0 - tx) + colours[y1][x1] * tx;
+    top * (1.0 - ty) + bottom * ty
+}
+
+impl PostPass for Fxaa {
+    fn name(&self) -> &'static str { "fxaa" }
+
+    fn apply(&self, frame: &mut Frame) {
+        let colours = &*frame;
+        let (width, height) = (colours[0].len(), colours.len());
+        let mut output = vec![Vec::new(); height];
+
+        output.par_iter_mut().enumerate().for_each(|(y, row)| {
+            row.reserve(width);
+            let up = y.saturating_sub(1);
+            let down = (y + 1).min(height - 1);
+
+            for x in 0..width {
+                let left = x.saturating_sub(1);
+                let right = (x + 1).min(width - 1);
+
+                let rgb_m = colours[y][x];
+                let luma_nw = LUMA.dot(colours[up][left]);
+                let luma_ne = LUMA.dot(colours[up][right]);
+                let luma_sw = LUMA.dot(colours[down][left]);
+                let luma_se = LUMA.dot(colours[down][right]);
+                let luma_m = LUMA.dot(rgb_m);
+
+                let luma_min = luma_m.min(luma_nw.min(luma_ne).min(luma_sw.min(luma_se)));
+                let luma_max = luma_m.max(luma_nw.max(luma_ne).max(luma_sw.max(luma_se)));
+
+                // points along the edge, so the blur doesn't cross it
+                let dir_x = -((luma_nw + luma_ne) - (luma_sw + luma_se));
+                let dir_y = (luma_nw + luma_sw) - (luma_ne + luma_se);
+
+                let dir_reduce =
+                    ((luma_nw + luma_ne + luma_sw + luma_se) * (0.25 * self.reduce_mul)).max(self.reduce_min);
+                let rcp_dir_min = (dir_x.abs().min(dir_y.abs()) + dir_reduce).recip();
+
+                let dir_x = (dir_x * rcp_dir_min).clamp(-self.span_max, self.span_max);
+                let dir_y = (dir_y * rcp_dir_min).clamp(-self.span_max, self.span_max);
+                let at = |t: f32| sample(colours, x as f32 + dir_x * t, y as f32 + dir_y * t);
+
+                let rgb_a = (at(1.0 / 3.0 - 0.5) + at(2.0 / 3.0 - 0.5)) * 0.5;
+                let rgb_b = rgb_a * 0.5 + (at(-0.5) + at(0.5)) * 0.25;
+
+                // the wider blur went past the edge, fall back to the narrow one
+                let luma_b = LUMA.dot(rgb_b);
+                row.push(if luma_b < luma_min || luma_b > luma_max {
+                    rgb_a
+                } else {
+                    rgb_b
+                });
+            }
+        });
+        *frame = output;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grey(v: f32) -> Colour { Colour::new(v, v, v) }
+
+    fn image(size: usize, pixel: impl Fn(usize, usize) -> f32) -> Vec<Vec<Colour>> {
+        (0..size)
+            .map(|y| (0..size).map(|x| grey(pixel(x, y))).collect())
+            .collect()
+    }
+
+    #[test]
+    fn flat_image_unchanged() {
+        let mut flat = image(8, |_, _| 0.5);
+        Fxaa::default().apply(&mut flat);
+        for row in flat {
+            for colour in row {
+                assert!((colour.x() - 0.5).abs() < 1e-6);
+            }
+        }
+    }
+
+    #[test]
+    fn softens_diagonal_edges() {
+        // a staircase, black below the diagonal and white above it
+        let stairs = image(16, |x, y| (x > y) as u32 as f32);
+        let mut smoothed = stairs.clone();
+        Fxaa::default().apply(&mut smoothed);
+
+        let mut softened = 0;
+        for (y, row) in smoothed.iter().enumerate() {
+            for (x, colour) in row.iter().enumerate() {
+                let v = colour.x();
+                assert!((0.0..=1.0).contains(&v));
+                if (x as isize - y as isize).abs() > 2 {
+                    // away from the edge nothing changes
+                    assert_eq!(v, stairs[y][x].x());
+                } else if v > 0.05 && v < 0.95 {
+                    softened += 1;
+                }
+            }
+        }
+        assert!(softened >= 16, "only {} pixels softened", softened);
+    }
+}
@@ -0,0 +1,25 @@
+#![feature(portable_simd)]
+#![feature(core_intrinsics)]
+
+pub mod bvh;
+pub mod camera;
+#[macro_use]
+pub mod colour;
+pub mod csg;
+pub mod engine;
+pub mod fractals;
+pub mod fxaa;
+pub mod lights;
+pub mod material;
+pub mod modifiers;
+pub mod objects;
+pub mod output;
+pub mod postprocess;
+pub mod radiosity;
+pub mod ray;
+pub mod sampling;
+pub mod scene;
+pub mod stats;
+pub mod texture;
+pub mod transform;
+pub mod vector;
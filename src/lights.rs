@@ -0,0 +1,144 @@
+use super::colour::Colour;
+use super::vector::Vec3;
+
+/// How a light looks from a point in the scene
+#[derive(Clone, Copy, Debug)]
+pub struct LightSample {
+    /// normalised vector from the point to the light
+    pub direction: Vec3,
+    /// distance to the light, infinite for a directional light
+    pub distance:  f32,
+    /// coloured intensity arriving at the point, after falloff and any cone
+    pub intensity: Colour,
+    /// tangent of the angle the light's surface covers, sets the penumbra width. 0 for a point
+    pub spread:    f32,
+}
+
+pub trait EngineLight {
+    fn get_position(&self) -> Vec3;
+    fn get_intensity(&self) -> f32;
+    fn get_colour(&self) -> Colour;
+
+    /// direction the light shines in, for lights that have one
+    fn get_direction(&self) -> Option<Vec3> { None }
+
+    /// radius of the emitting surface, 0 for a point
+    fn get_radius(&self) -> f32 { 0.0 }
+
+    // attenuation at a given distance from the light, inverse square by default
+    fn falloff(&self, distance: f32) -> f32 { distance.max(self.get_radius()).powi(2).recip() }
+
+    // how much of the light reaches `direction` (pointing away from the light), for cones
+    fn cone(&self, _direction: Vec3) -> f32 { 1.0 }
+
+    /// Sample the light as seen from `position`
+    fn sample(&self, position: Vec3) -> LightSample {
+        let vector_to_light = self.get_position() - position;
+        let distance = vector_to_light.mag();
+        let direction = vector_to_light / distance;
+
+        LightSample {
+            direction,
+            distance,
+            intensity: self.get_colour() * (self.get_intensity() * self.falloff(distance) * self.cone(-direction)),
+            spread: self.get_radius() / distance,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct PointLight {
+    pub position:  Vec3,
+    pub intensity: f32,
+    pub colour:    Colour,
+}
+
+/// A light infinitely far away, like the sun. Its angular radius softens shadows
+#[derive(Clone, Copy)]
+pub struct DirectionalLight {
+    pub direction:      Vec3,
+    pub intensity:      f32,
+    pub colour:         Colour,
+    pub angular_radius: f32,
+}
+
+/// A point light that only shines within a cone. Full intensity inside the inner angle, fading to nothing at the
+/// outer angle. Angles are measured from the direction, in radians
+#[derive(Clone, Copy)]
+pub struct SpotLight {
+    pub position:    Vec3,
+    pub direction:   Vec3,
+    pub intensity:   f32,
+    pub colour:      Colour,
+    pub inner_angle: f32,
+    pub outer_angle: f32,
+    pub radius:      f32,
+}
+
+/// A spherical area light
+#[derive(Clone, Copy)]
+pub struct SphereLight {
+    pub position:  Vec3,
+    pub radius:    f32,
+    pub intensity: f32,
+    pub colour:    Colour,
+}
+
+impl EngineLight for PointLight {
+    fn get_position(&self) -> Vec3 { self.position }
+
+    fn get_intensity(&self) -> f32 { self.intensity }
+
+    fn get_colour(&self) -> Colour { self.colour }
+}
+
+impl EngineLight for DirectionalLight {
+    // there is no real position, this is just somewhere the light comes from
+    fn get_position(&self) -> Vec3 { self.direction * -f32::MAX.sqrt() }
+
+    fn get_intensity(&self) -> f32 { self.intensity }
+
+    fn get_colour(&self) -> Colour { self.colour }
+
+    fn get_direction(&self) -> Option<Vec3> { Some(self.direction) }
+
+    fn falloff(&self, _distance: f32) -> f32 { 1.0 }
+
+    fn sample(&self, _position: Vec3) -> LightSample {
+        LightSample {
+            direction: -self.direction,
+            distance:  f32::INFINITY,
+            intensity: self.colour * self.intensity,
+            spread:    self.angular_radius.tan(),
+        }
+    }
+}
+
+impl EngineLight for SpotLight {
+    fn get_position(&self) -> Vec3 { self.position }
+
+    fn get_intensity(&self) -> f32 { self.intensity }
+
+    fn get_colour(&self) -> Colour { self.colour }
+
+    fn get_direction(&self) -> Option<Vec3> { Some(self.direction) }
+
+    fn get_radius(&self) -> f32 { self.radius }
+
+    fn cone(&self, direction: Vec3) -> f32 {
+        let (cos_inner, cos_outer) = (self.inner_angle.cos(), self.outer_angle.cos());
+        let x = ((direction.dot(self.direction) - cos_outer) / (cos_inner - cos_outer).max(1e-6)).clamp(0.0, 1.0);
+        // smoothstep
+        3.0 * x.powi(2) - 2.0 * x.powi(3)
+    }
+}
+
+impl EngineLight for SphereLight {
+    fn get_position(&self) -> Vec3 { self.position }
+
+    fn get_intensity(&self) -> f32 { self.intensity }
+
+    fn get_colour(&self) -> Colour { self.colour }
+
+    fn get_radius(&self) -> f32 { self.radius }
+}
@@ -1,44 +1,131 @@
-#![feature(portable_simd)]
-#![feature(core_intrinsics)]
-
-#[macro_use]
-mod colour;
-mod engine;
-mod material;
-mod objects;
-mod radiosity;
-mod ray;
-mod texture;
-mod vector;
-
 extern crate sdl2;
 
-use colour::{SOFT_GRAY, SOFT_GREEN, SOFT_RED, SOFT_YELLOW, WHITE};
-use engine::{Engine, HEIGHT, WIDTH};
-use material::Material;
-use objects::{EngineObject, PointLight, Sphere, XPlane, YPlane, ZPlane};
-use sdl2::event::Event;
+use ray_marching::engine::{Engine, RenderTarget, DEFAULT_HEIGHT, DEFAULT_WIDTH};
+use ray_marching::output::save_bgra;
+use ray_marching::scene::{load_scene, DEFAULT_SCENE};
+use ray_marching::stats::{Profiler, Stage};
+use ray_marching::vector::Vec3;
+use sdl2::event::{Event, WindowEvent};
 use sdl2::keyboard::{Keycode, Scancode};
 use sdl2::mouse::MouseButton;
-use std::time::Instant;
-use vector::Vec3;
-
-use crate::engine::Aligned;
-use crate::texture::Texture;
+use std::f32::consts::FRAC_PI_2;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 #[global_allocator]
 static GLOBAL: mimalloc::MiMalloc = mimalloc::MiMalloc;
 
+// radians of camera rotation per pixel of mouse movement
+const MOUSE_SENSITIVITY: f32 = 1.0 / 600.0;
+// radians of camera roll per frame while Q or E is held
+const ROLL_SPEED: f32 = 0.02;
+// toggle the post-process pass at the same position
+const PASS_KEYS: [Keycode; 9] = [
+    Keycode::Num1,
+    Keycode::Num2,
+    Keycode::Num3,
+    Keycode::Num4,
+    Keycode::Num5,
+    Keycode::Num6,
+    Keycode::Num7,
+    Keycode::Num8,
+    Keycode::Num9,
+];
+// supersampling used for high resolution captures, at least
+const CAPTURE_SAMPLES: u32 = 16;
+const MAX_CAPTURE_SCALE: usize = 8;
+// frames the timing summary covers, and how often it's printed
+const PROFILE_FRAMES: usize = 120;
+const SUMMARY_INTERVAL: Duration = Duration::from_secs(5);
+
+/// `{prefix}-YYYYMMDD-HHMMSS-mmm.png` in the working directory, from the UTC time
+fn timestamped_path(prefix: &str) -> String {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    let (days, secs) = ((now.as_secs() / 86400) as i64, now.as_secs() % 86400);
+
+    // days since 1970 to a date, from Howard Hinnant's civil_from_days
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z - era * 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+
+    format!(
+        "{}-{:04}{:02}{:02}-{:02}{:02}{:02}-{:03}.png",
+        prefix,
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60,
+        now.subsec_millis()
+    )
+}
+
+/// Render the current view offscreen at `scale` times the window size, with more supersampling, and save it
+fn capture(engine: &mut Engine, width: usize, height: usize, scale: usize) {
+    let (width, height) = (width * scale, height * scale);
+    let mut target = RenderTarget::new(width, height);
+    let mut buffer = vec![0u8; width * height * 4];
+
+    let ssaa = engine.ssaa;
+    engine.ssaa.samples = ssaa.samples.max(CAPTURE_SAMPLES);
+    let now = Instant::now();
+    engine.render(&mut buffer, width * 4, &mut target);
+    engine.ssaa = ssaa;
+
+    let path = timestamped_path("capture");
+    match save_bgra(&path, &buffer, width * 4, width, height) {
+        Ok(()) => println!(
+            "Saved {}x{} capture to {} in {:.2?}",
+            width,
+            height,
+            path,
+            now.elapsed()
+        ),
+        Err(e) => eprintln!("failed to write {}: {}", path, e),
+    }
+}
+
 fn main() {
     println!("Hello, world!");
 
+    // ray_marching [SCENE] [--stats-csv FILE]
+    let mut scene_path = DEFAULT_SCENE.to_string();
+    let mut profiler = Profiler::new(PROFILE_FRAMES);
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--stats-csv" => {
+                let path = args.next().unwrap_or_else(|| {
+                    eprintln!("--stats-csv needs a file");
+                    std::process::exit(2);
+                });
+                if let Err(e) = profiler.log_csv(&path) {
+                    eprintln!("{}: {}", path, e);
+                    std::process::exit(1);
+                }
+            }
+            _ => scene_path = arg,
+        }
+    }
+    let mut engine = load_scene(&scene_path).unwrap_or_else(|e| {
+        eprintln!("{}: {}", scene_path, e);
+        std::process::exit(1);
+    });
+
     let sdl_context = sdl2::init().unwrap();
     let video_subsystem = sdl_context.video().unwrap();
 
     // create window
     let window = video_subsystem
-        .window("ray marching", WIDTH as u32, HEIGHT as u32)
+        .window("ray marching", DEFAULT_WIDTH as u32, DEFAULT_HEIGHT as u32)
         .position_centered()
+        .resizable()
         .build()
         .unwrap();
 
@@ -54,36 +141,49 @@ fn main() {
     let mut texture = texture_creator
         .create_texture_streaming(
             Some(sdl2::pixels::PixelFormatEnum::ARGB8888),
-            WIDTH as u32,
-            HEIGHT as u32,
+            DEFAULT_WIDTH as u32,
+            DEFAULT_HEIGHT as u32,
         )
         .unwrap();
 
-    let objs = construct_objects();
-
-    let mut engine = Engine {
-        objects:         objs,
-        camera_position: Vec3::new(0.0, 0.5, -3.5),
-        light:           PointLight {
-            position:  Vec3::new(2.0, -1.0, 1.5),
-            intensity: 3.5,
-        },
-    };
-
     engine.compute_lightmaps();
-    let mut directions = Aligned(vec![vec![Vec3::default(); WIDTH]; HEIGHT]);
+    for (i, stage) in engine.post.stages.iter().enumerate().take(PASS_KEYS.len()) {
+        println!(
+            "{}: {} ({})",
+            i + 1,
+            stage.pass.name(),
+            if stage.enabled { "on" } else { "off" }
+        );
+    }
+    let mut capture_scale = 2;
+    println!(
+        "P: screenshot, C: capture at {}x, [ and ]: change capture size",
+        capture_scale
+    );
+    let mut take_screenshot = false;
+    let mut frame_start = Instant::now();
+    let mut last_summary = Instant::now();
+    let mut target = RenderTarget::new(DEFAULT_WIDTH, DEFAULT_HEIGHT);
 
     rayon::ThreadPoolBuilder::new().num_threads(10).build_global().unwrap();
 
-    let mut mouse_x: i32 = 0;
-    let mut mouse_y: i32 = 0;
-    let mut scroll: i32 = 0;
-
     'running: loop {
         for event in event_pump.poll_iter() {
             match event {
                 Event::Quit { .. } => break 'running,
 
+                Event::Window {
+                    win_event: WindowEvent::SizeChanged(width, height),
+                    ..
+                } => {
+                    // render at the new window size, so the aspect ratio follows it
+                    let (width, height) = (width.max(1) as u32, height.max(1) as u32);
+                    texture = texture_creator
+                        .create_texture_streaming(Some(sdl2::pixels::PixelFormatEnum::ARGB8888), width, height)
+                        .unwrap();
+                    target.resize(width as usize, height as usize);
+                }
+
                 Event::KeyDown {
                     keycode: Some(Keycode::Escape),
                     ..
@@ -93,6 +193,49 @@ fn main() {
                     sdl_context.mouse().show_cursor(true);
                 }
 
+                Event::KeyDown {
+                    keycode: Some(Keycode::F),
+                    repeat: false,
+                    ..
+                } => match engine.post.toggle_named("fxaa") {
+                    Some(enabled) => println!("FXAA {}", if enabled { "on" } else { "off" }),
+                    None => println!("no FXAA pass in this scene"),
+                },
+
+                Event::KeyDown {
+                    keycode: Some(Keycode::P),
+                    repeat: false,
+                    ..
+                } => take_screenshot = true,
+
+                Event::KeyDown {
+                    keycode: Some(Keycode::C),
+                    repeat: false,
+                    ..
+                } => capture(&mut engine, target.width, target.height, capture_scale),
+
+                Event::KeyDown {
+                    keycode: Some(keycode @ (Keycode::LeftBracket | Keycode::RightBracket)),
+                    ..
+                } => {
+                    capture_scale = match keycode {
+                        Keycode::LeftBracket => capture_scale.saturating_sub(1).max(1),
+                        _ => (capture_scale + 1).min(MAX_CAPTURE_SCALE),
+                    };
+                    println!("Capture at {}x", capture_scale);
+                }
+
+                Event::KeyDown {
+                    keycode: Some(keycode),
+                    repeat: false,
+                    ..
+                } if PASS_KEYS.contains(&keycode) => {
+                    let index = PASS_KEYS.iter().position(|&key| key == keycode).unwrap();
+                    if let Some(stage) = engine.post.toggle(index) {
+                        println!("{} {}", stage.pass.name(), if stage.enabled { "on" } else { "off" });
+                    }
+                }
+
                 Event::MouseButtonDown {
                     mouse_btn: MouseButton::Left,
                     ..
@@ -105,13 +248,15 @@ fn main() {
                 Event::MouseMotion { xrel, yrel, .. } => {
                     //if relative mouse mode true, mouse is captured
                     if sdl_context.mouse().relative_mouse_mode() {
-                        mouse_x += xrel;
-                        mouse_y += yrel;
+                        let camera = &mut engine.camera;
+                        camera.yaw += xrel as f32 * MOUSE_SENSITIVITY;
+                        camera.pitch = (camera.pitch - yrel as f32 * MOUSE_SENSITIVITY).clamp(-FRAC_PI_2, FRAC_PI_2);
                     }
                 }
                 Event::MouseWheel { y, .. } => {
+                    // change fov by 5 deg each scroll step
                     if sdl_context.mouse().relative_mouse_mode() {
-                        scroll += y;
+                        engine.camera.fov = (engine.camera.fov - y as f32 * 5.0).clamp(5.0, 170.0);
                     }
                 }
 
@@ -133,61 +278,49 @@ fn main() {
                 Scancode::S => rel_move.0[2] -= speed,
                 Scancode::A => rel_move.0[0] -= speed,
                 Scancode::D => rel_move.0[0] += speed,
+                Scancode::Q => engine.camera.roll -= ROLL_SPEED,
+                Scancode::E => engine.camera.roll += ROLL_SPEED,
                 _ => {}
             }
         }
+        // move camera in direction we are facing
+        engine.camera.move_relative(rel_move);
 
-        let now = Instant::now();
-        texture
-            .with_lock(None, |buffer, _width| {
-                engine.render(buffer, &mut directions, mouse_x, mouse_y, rel_move, scroll);
+        let mut stats = texture
+            .with_lock(None, |buffer, pitch| {
+                let stats = engine.render(buffer, pitch, &mut target);
+                // save straight from the texture, it's exactly what's about to be shown
+                if take_screenshot {
+                    let path = timestamped_path("screenshot");
+                    match save_bgra(&path, buffer, pitch, target.width, target.height) {
+                        Ok(()) => println!("Saved {}", path),
+                        Err(e) => eprintln!("failed to write {}: {}", path, e),
+                    }
+                }
+                stats
             })
             .unwrap(); // update texture
+        take_screenshot = false;
 
+        let now = Instant::now();
         canvas.copy(&texture, None, None).unwrap();
-        let elapsed = now.elapsed();
-        println!("Elapsed: {:.2?}", elapsed);
         canvas.present();
-    }
-}
+        stats.times[Stage::Blit] += now.elapsed();
+
+        if let Err(e) = profiler.record(stats.times, frame_start.elapsed()) {
+            eprintln!("stopped writing stats: {}", e);
+        }
+        frame_start = Instant::now();
 
-fn construct_objects() -> Vec<Box<dyn EngineObject>> {
-    let wood_tex = Texture::new("assets/textures/Floor128.bmp", 32.0, 32.0);
-    const BASIC_MAT: Material = Material::basic();
-    vec![
-        Box::new(Sphere {
-            position: Vec3::new(-1.2, -1.0, 0.1),
-            radius:   1.0,
-            material: Material {
-                ambient:      0.05,
-                diffuse:      0.03,
-                specular:     0.2,
-                shininess:    16.0,
-                reflectivity: 1.0,
-                emissive:     0.0,
-            },
-            colour:   WHITE,
-            lightmap: Default::default(),
-        }),
-        Box::new(Sphere {
-            position: Vec3::new(1.0, -1.0, -0.7),
-            radius:   1.0,
-            material: Material {
-                ambient:      0.1,
-                diffuse:      1.0,
-                specular:     0.9,
-                shininess:    32.0,
-                reflectivity: 0.25,
-                emissive:     0.0,
-            },
-            colour:   SOFT_YELLOW,
-            lightmap: Default::default(),
-        }),
-        Box::new(YPlane::new(-2.0, 1.0, BASIC_MAT, SOFT_GRAY, &wood_tex)),
-        Box::new(YPlane::new(4.0, -1.0, BASIC_MAT, SOFT_GRAY, &wood_tex)),
-        Box::new(XPlane::new(-3.0, 1.0, BASIC_MAT, SOFT_RED)),
-        Box::new(XPlane::new(3.0, -1.0, BASIC_MAT, SOFT_GREEN)),
-        Box::new(ZPlane::new(2.0, -1.0, BASIC_MAT, SOFT_GRAY)),
-        Box::new(ZPlane::new(-4.0, 1.0, BASIC_MAT, SOFT_GRAY)),
-    ]
+        if last_summary.elapsed() >= SUMMARY_INTERVAL {
+            println!(
+                "samples: {}, bounce depth: {:.2} mean, {} max\n{}",
+                target.samples,
+                stats.mean_depth(),
+                stats.max_depth,
+                profiler.summary()
+            );
+            last_summary = Instant::now();
+        }
+    }
 }
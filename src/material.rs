@@ -1,3 +1,5 @@
+use super::colour::Colour;
+
 #[derive(Clone, Copy)]
 pub struct Material {
     pub ambient:      f32,
@@ -6,6 +8,12 @@ pub struct Material {
     pub shininess:    f32, // aka gloss
     pub reflectivity: f32,
     pub emissive:     f32,
+    /// index of refraction, 1.5 for glass and 1.33 for water
+    pub ior:          f32,
+    /// how much light passes through the surface rather than being scattered by it, 0 is opaque
+    pub transmission: f32,
+    /// Beer-Lambert absorption per unit distance travelled inside the object, for each colour channel
+    pub absorption:   Colour,
 }
 
 impl Material {
@@ -17,10 +25,89 @@ impl Material {
             shininess:    4.0,
             reflectivity: 0.0,
             emissive:     0.0,
+            ior:          1.0,
+            transmission: 0.0,
+            absorption:   Colour::new(0.0, 0.0, 0.0),
+        }
+    }
+
+    /// Blend towards `other`, 0 is all this material and 1 all the other
+    pub fn lerp(&self, other: &Material, t: f32) -> Material {
+        let mix = |a: f32, b: f32| a + (b - a) * t;
+        Material {
+            ambient:      mix(self.ambient, other.ambient),
+            diffuse:      mix(self.diffuse, other.diffuse),
+            specular:     mix(self.specular, other.specular),
+            shininess:    mix(self.shininess, other.shininess),
+            reflectivity: mix(self.reflectivity, other.reflectivity),
+            emissive:     mix(self.emissive, other.emissive),
+            ior:          mix(self.ior, other.ior),
+            transmission: mix(self.transmission, other.transmission),
+            absorption:   self.absorption * (1.0 - t) + other.absorption * t,
+        }
+    }
+
+    /// Fraction of light reflected when entering the material from air, at an angle with this cosine to the normal.
+    /// Unpolarised Fresnel equations for a dielectric
+    pub fn fresnel(&self, cos_incident: f32) -> f32 {
+        let cos_i = cos_incident.clamp(0.0, 1.0);
+        let sin_t = (1.0 - cos_i.powi(2)).sqrt() / self.ior;
+        if sin_t >= 1.0 {
+            return 1.0;
         }
+        let cos_t = (1.0 - sin_t.powi(2)).sqrt();
+
+        let parallel = (self.ior * cos_i - cos_t) / (self.ior * cos_i + cos_t);
+        let perpendicular = (cos_i - self.ior * cos_t) / (cos_i + self.ior * cos_t);
+        0.5 * (parallel.powi(2) + perpendicular.powi(2))
+    }
+
+    /// Fraction of each colour that survives travelling `distance` through the material
+    pub fn transmittance(&self, distance: f32) -> Colour {
+        let optical_depth = self.absorption * -distance;
+        Colour::new(
+            optical_depth.x().exp(),
+            optical_depth.y().exp(),
+            optical_depth.z().exp(),
+        )
     }
 }
 
 impl Default for Material {
     fn default() -> Self { Material::basic() }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn glass() -> Material {
+        Material {
+            ior: 1.5,
+            transmission: 1.0,
+            absorption: Colour::new(1.0, 0.0, 2.0),
+            ..Material::basic()
+        }
+    }
+
+    #[test]
+    fn fresnel() {
+        // ((n - 1) / (n + 1))² head on, and everything reflects at a grazing angle
+        assert!((glass().fresnel(1.0) - 0.04).abs() < 1e-6);
+        assert!((glass().fresnel(0.0) - 1.0).abs() < 1e-6);
+        assert!(glass().fresnel(0.5) > glass().fresnel(0.9));
+
+        // nothing to reflect off without a change in index
+        assert!(Material::basic().fresnel(0.3).abs() < 1e-6);
+    }
+
+    #[test]
+    fn transmittance() {
+        let clear = glass().transmittance(0.0);
+        assert_eq!((clear.x(), clear.y(), clear.z()), (1.0, 1.0, 1.0));
+        let through = glass().transmittance(0.5);
+        assert!((through.x() - (-0.5f32).exp()).abs() < 1e-6);
+        assert_eq!(through.y(), 1.0);
+        assert!((through.z() - (-1f32).exp()).abs() < 1e-6);
+    }
+}
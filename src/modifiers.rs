@@ -0,0 +1,291 @@
+//! Domain modifiers. `Modified` repeats, mirrors, bends or reshapes the object it wraps, by changing where the object
+//! is sampled or the distance it gives back. They all work about the origin, so build the object there and move the
+//! result with a `Transformed`.
+
+use crate::bvh::Aabb;
+use crate::colour::Colour;
+use crate::material::Material;
+use crate::objects::{globe_direction, globe_uv, outer_surface_point, EngineObject};
+use crate::radiosity::Lightmap;
+use crate::vector::Vec3;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Modifier {
+    /// a copy every `spacing` along each axis, forever. a spacing of 0 leaves that axis alone. the object should fit
+    /// inside one spacing, centred on the origin
+    Repeat { spacing: Vec3 },
+    /// like `Repeat`, but only `limit` more copies either side of the original along each axis
+    RepeatFinite { spacing: Vec3, limit: Vec3 },
+    /// copy the positive side of each marked axis onto the negative side
+    Mirror { x: bool, y: bool, z: bool },
+    /// turn about the y axis by `rate` radians per unit up it
+    Twist { rate: f32 },
+    /// curl the x axis around the z axis, turning `rate` radians per unit along x
+    Bend { rate: f32 },
+    /// pull the object apart at the origin, by `half_extent` each way along each axis
+    Elongate { half_extent: Vec3 },
+    /// grow the surface out by `radius`, rounding off edges and corners
+    Round { radius: f32 },
+    /// hollow the object out into a shell `thickness` either side of its surface
+    Onion { thickness: f32 },
+}
+
+/// Fold one coordinate into the copy around 0
+fn repeat_axis(x: f32, spacing: f32, limit: Option<f32>) -> f32 {
+    if spacing == 0.0 {
+        return x;
+    }
+    let cell = (x / spacing).round();
+    x - spacing * limit.map_or(cell, |limit| cell.clamp(-limit, limit))
+}
+
+pub struct Modified<T: EngineObject> {
+    object:       T,
+    modifier:     Modifier,
+    /// for twists and bends, how far the object reaches from the axis it's turned about. None if it's infinite
+    reach:        Option<f32>,
+    pub lightmap: Lightmap,
+}
+
+impl<T: EngineObject> Modified<T> {
+    pub fn new(object: T, modifier: Modifier) -> Self {
+        // furthest corner of the bounds from the turning axis
+        let reach = object.bounds().map(|bounds| {
+            let far = bounds.min.abs().max(bounds.max.abs());
+            match modifier {
+                Modifier::Bend { .. } => far.x().hypot(far.y()),
+                _ => far.x().hypot(far.z()),
+            }
+        });
+        Self {
+            object,
+            modifier,
+            reach,
+            lightmap: Lightmap::default(),
+        }
+    }
+
+    pub fn object(&self) -> &T { &self.object }
+    pub fn modifier(&self) -> Modifier { self.modifier }
+
+    /// Where to sample the object for a point in world space
+    fn local(&self, position: Vec3) -> Vec3 {
+        let p = position;
+        match self.modifier {
+            Modifier::Repeat { spacing } => Vec3::new(
+                repeat_axis(p.x(), spacing.x(), None),
+                repeat_axis(p.y(), spacing.y(), None),
+                repeat_axis(p.z(), spacing.z(), None),
+            ),
+            Modifier::RepeatFinite { spacing, limit } => Vec3::new(
+                repeat_axis(p.x(), spacing.x(), Some(limit.x())),
+                repeat_axis(p.y(), spacing.y(), Some(limit.y())),
+                repeat_axis(p.z(), spacing.z(), Some(limit.z())),
+            ),
+            Modifier::Mirror { x, y, z } => {
+                let fold = |v: f32, mirrored: bool| if mirrored { v.abs() } else { v };
+                Vec3::new(fold(p.x(), x), fold(p.y(), y), fold(p.z(), z))
+            }
+            Modifier::Twist { rate } => {
+                let (sin, cos) = (rate * p.y()).sin_cos();
+                Vec3::new(cos * p.x() - sin * p.z(), p.y(), sin * p.x() + cos * p.z())
+            }
+            Modifier::Bend { rate } => {
+                let (sin, cos) = (rate * p.x()).sin_cos();
+                Vec3::new(cos * p.x() - sin * p.y(), sin * p.x() + cos * p.y(), p.z())
+            }
+            Modifier::Elongate { half_extent } => p - p.max(-half_extent).min(half_extent),
+            Modifier::Round { .. } | Modifier::Onion { .. } => p,
+        }
+    }
+
+    /// The most a twist or bend stretches space by, near the object. Distances are divided by it so marching never
+    /// oversteps, at the cost of more steps
+    fn stretch(&self, position: Vec3, rate: f32) -> f32 {
+        let twist = matches!(self.modifier, Modifier::Twist { .. });
+        let reach = self.reach.unwrap_or_else(|| match twist {
+            true => position.x().hypot(position.z()),
+            false => position.x().hypot(position.y()),
+        });
+        match twist {
+            true => (1.0 + (rate * reach).powi(2)).sqrt(),
+            false => 1.0 + (rate * reach).abs(),
+        }
+    }
+}
+
+impl<T: EngineObject> EngineObject for Modified<T> {
+    fn sdf(&self, position: Vec3) -> f32 {
+        match self.modifier {
+            Modifier::Round { radius } => self.object.sdf(position) - radius,
+            Modifier::Onion { thickness } => self.object.sdf(position).abs() - thickness,
+            Modifier::Twist { rate } | Modifier::Bend { rate } => {
+                self.object.sdf(self.local(position)) / self.stretch(position, rate)
+            }
+            _ => self.object.sdf(self.local(position)),
+        }
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        let bounds = self.object.bounds()?;
+        Some(match self.modifier {
+            Modifier::Repeat { spacing } if spacing == Vec3::default() => bounds,
+            Modifier::Repeat { .. } => return None,
+            Modifier::RepeatFinite { spacing, limit } => bounds.expanded(spacing.abs().element_mul(limit)),
+            Modifier::Mirror { x, y, z } => {
+                let far = bounds.min.abs().max(bounds.max.abs());
+                let pick = |mirrored: bool, far: f32, min: f32, max: f32| match mirrored {
+                    true => (-far, far),
+                    false => (min, max),
+                };
+                let (x0, x1) = pick(x, far.x(), bounds.min.x(), bounds.max.x());
+                let (y0, y1) = pick(y, far.y(), bounds.min.y(), bounds.max.y());
+                let (z0, z1) = pick(z, far.z(), bounds.min.z(), bounds.max.z());
+                Aabb::new(Vec3::new(x0, y0, z0), Vec3::new(x1, y1, z1))
+            }
+            // turning keeps the distance from the axis
+            Modifier::Twist { .. } => {
+                let reach = self.reach.unwrap();
+                Aabb::new(
+                    Vec3::new(-reach, bounds.min.y(), -reach),
+                    Vec3::new(reach, bounds.max.y(), reach),
+                )
+            }
+            Modifier::Bend { .. } => {
+                let reach = self.reach.unwrap();
+                Aabb::new(
+                    Vec3::new(-reach, -reach, bounds.min.z()),
+                    Vec3::new(reach, reach, bounds.max.z()),
+                )
+            }
+            Modifier::Elongate { half_extent } => bounds.expanded(half_extent),
+            Modifier::Round { radius: grow } | Modifier::Onion { thickness: grow } => {
+                bounds.expanded(Vec3::new(grow, grow, grow))
+            }
+        })
+    }
+
+    fn colour(&self, position: Vec3) -> Colour { self.object.colour(self.local(position)) }
+    fn material(&self) -> &Material { self.object.material() }
+    fn material_at(&self, position: Vec3) -> Material { self.object.material_at(self.local(position)) }
+
+    fn radiosity_collide(&self) -> bool { self.object.radiosity_collide() }
+
+    // one lightmap wrapped around everything, like a csg tree. infinite repeats go without
+    fn get_lightmap(&self) -> Option<&Lightmap> { self.bounds().map(|_| &self.lightmap) }
+    fn set_lightmap(&mut self, new_lightmap: Lightmap) { self.lightmap = new_lightmap; }
+    fn clear_lightmap(&mut self) { self.lightmap = Lightmap::default() }
+
+    fn get_sample_pos(&self, u: usize, v: usize) -> Vec3 {
+        outer_surface_point(self, self.bounds().unwrap(), globe_direction(u, v))
+    }
+    fn sample_uv_from_pos(&self, pos: Vec3) -> (f32, f32) { globe_uv(pos - self.bounds().unwrap().centre()) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::colour::WHITE;
+    use crate::objects::Cuboid;
+
+    /// A box off to one side of the origin, so mirrors and turns move it
+    fn cuboid() -> Cuboid {
+        Cuboid {
+            position:  Vec3::new(1.0, 0.5, -0.2),
+            half_size: Vec3::new(0.5, 0.25, 0.3),
+            material:  Material::basic(),
+            colour:    WHITE,
+            lightmap:  Default::default(),
+        }
+    }
+
+    fn modifiers() -> Vec<Modifier> {
+        vec![
+            Modifier::RepeatFinite {
+                spacing: Vec3::new(3.0, 0.0, 1.0),
+                limit:   Vec3::new(1.0, 0.0, 2.0),
+            },
+            Modifier::Mirror {
+                x: true,
+                y: false,
+                z: true,
+            },
+            Modifier::Twist { rate: 1.5 },
+            Modifier::Bend { rate: -0.8 },
+            Modifier::Elongate {
+                half_extent: Vec3::new(0.5, 1.0, 0.0),
+            },
+            Modifier::Round { radius: 0.2 },
+            Modifier::Onion { thickness: 0.1 },
+        ]
+    }
+
+    /// Points on a grid around `bounds`, reaching past them on every side
+    fn around(bounds: Aabb) -> impl Iterator<Item = Vec3> {
+        const STEPS: usize = 30;
+        let size = bounds.max - bounds.min;
+        let corner = bounds.min - size * 0.25;
+        (0..(STEPS + 1).pow(3)).map(move |i| {
+            let t = |n: usize| (n % (STEPS + 1)) as f32 / STEPS as f32 * 1.5;
+            corner + size.element_mul(Vec3::new(t(i), t(i / (STEPS + 1)), t(i / (STEPS + 1).pow(2))))
+        })
+    }
+
+    #[test]
+    fn repeat_axis_folds() {
+        assert_eq!(repeat_axis(3.7, 0.0, None), 3.7);
+        assert_eq!(repeat_axis(3.7, 0.0, Some(1.0)), 3.7);
+        assert!((repeat_axis(3.5, 2.0, None) - -0.5).abs() < 1e-6);
+        assert!((repeat_axis(-3.5, 2.0, None) - 0.5).abs() < 1e-6);
+
+        // past the last copy it keeps measuring from that copy
+        assert!((repeat_axis(7.0, 2.0, Some(1.0)) - 5.0).abs() < 1e-6);
+        assert!((repeat_axis(-7.0, 2.0, Some(1.0)) - -5.0).abs() < 1e-6);
+        assert!((repeat_axis(2.5, 2.0, Some(1.0)) - 0.5).abs() < 1e-6);
+        assert_eq!(repeat_axis(7.0, 2.0, Some(0.0)), 7.0);
+    }
+
+    #[test]
+    fn bounds_contain_surface() {
+        for modifier in modifiers() {
+            let modified = Modified::new(cuboid(), modifier);
+            let bounds = modified.bounds().unwrap();
+            for position in around(bounds).filter(|&position| modified.sdf(position) <= 0.0) {
+                assert!(
+                    bounds.distance(position) < 1e-4,
+                    "{:?}: {} out of bounds",
+                    modifier,
+                    position
+                );
+            }
+        }
+
+        assert!(Modified::new(
+            cuboid(),
+            Modifier::Repeat {
+                spacing: Vec3::new(2.0, 0.0, 0.0),
+            }
+        )
+        .bounds()
+        .is_none());
+    }
+
+    #[test]
+    fn turns_never_overestimate() {
+        // dividing by the stretch keeps the distance from changing faster than the position does
+        for modifier in [Modifier::Twist { rate: 1.5 }, Modifier::Bend { rate: -0.8 }] {
+            let modified = Modified::new(cuboid(), modifier);
+            let bounds = modified.bounds().unwrap();
+            for position in around(bounds).step_by(7) {
+                for &offset in &[
+                    Vec3::new(0.05, 0.0, 0.0),
+                    Vec3::new(0.0, 0.05, 0.0),
+                    Vec3::new(0.0, 0.0, 0.05),
+                ] {
+                    let change = (modified.sdf(position + offset) - modified.sdf(position)).abs();
+                    assert!(change <= offset.mag() * 1.001, "{:?} at {}", modifier, position);
+                }
+            }
+        }
+    }
+}
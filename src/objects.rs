@@ -1,5 +1,6 @@
 use std::f32::consts::{PI, TAU};
 
+use crate::bvh::Aabb;
 use crate::texture::Texture;
 
 use super::radiosity::MAP_SIZE;
@@ -15,6 +16,8 @@ const X_STEP: Vec3 = Vec3::new(STEP_SIZE, 0.0, 0.0);
 const Y_STEP: Vec3 = Vec3::new(0.0, STEP_SIZE, 0.0);
 const Z_STEP: Vec3 = Vec3::new(0.0, 0.0, STEP_SIZE);
 
+const MAX_SURFACE_STEPS: usize = 256;
+
 /*
 const TETRA_STEP: f32 = 0.0015;
 const STEP_A: Vec3 = Vec3::new(TETRA_STEP, -TETRA_STEP, -TETRA_STEP);
@@ -27,9 +30,14 @@ pub trait EngineObject {
     fn sdf(&self, position: Vec3) -> f32;
     fn colour(&self, position: Vec3) -> Colour;
     fn material(&self) -> &Material;
+    // the material at a point on the surface, for objects made of more than one
+    fn material_at(&self, _position: Vec3) -> Material { *self.material() }
 
     fn radiosity_collide(&self) -> bool { false }
 
+    // box containing the whole object, None if it is infinite
+    fn bounds(&self) -> Option<Aabb> { None }
+
     // all objects have a default implementation of no lightmap
     fn get_lightmap(&self) -> Option<&Lightmap> { None }
     fn set_lightmap(&mut self, _new_lightmap: Lightmap) {}
@@ -150,9 +158,25 @@ pub trait EngineObject {
     }
 }
 
-pub trait EngineLight {
-    fn get_position(&self) -> Vec3;
-    fn get_intensity(&self) -> f32;
+// so wrappers like `Transformed` can hold any object
+impl<T: EngineObject + ?Sized> EngineObject for Box<T> {
+    fn sdf(&self, position: Vec3) -> f32 { (**self).sdf(position) }
+    fn colour(&self, position: Vec3) -> Colour { (**self).colour(position) }
+    fn material(&self) -> &Material { (**self).material() }
+    fn material_at(&self, position: Vec3) -> Material { (**self).material_at(position) }
+
+    fn radiosity_collide(&self) -> bool { (**self).radiosity_collide() }
+    fn bounds(&self) -> Option<Aabb> { (**self).bounds() }
+
+    fn get_lightmap(&self) -> Option<&Lightmap> { (**self).get_lightmap() }
+    fn set_lightmap(&mut self, new_lightmap: Lightmap) { (**self).set_lightmap(new_lightmap) }
+    fn clear_lightmap(&mut self) { (**self).clear_lightmap() }
+
+    fn get_sample_pos(&self, u: usize, v: usize) -> Vec3 { (**self).get_sample_pos(u, v) }
+    fn sample_uv_from_pos(&self, pos: Vec3) -> (f32, f32) { (**self).sample_uv_from_pos(pos) }
+    fn sample_lightmap(&self, pos: Vec3) -> Colour { (**self).sample_lightmap(pos) }
+
+    fn calculate_normal(&self, position: Vec3) -> Vec3 { (**self).calculate_normal(position) }
 }
 
 #[derive(Clone, Copy)]
@@ -190,7 +214,7 @@ pub struct YPlane {
     pub material: Material,
     pub colour:   Colour,
     pub lightmap: Lightmap,
-    pub texture:  Texture,
+    pub texture:  Option<Texture>,
 }
 
 #[derive(Clone, Copy, Default)]
@@ -202,12 +226,6 @@ pub struct ZPlane {
     pub lightmap: Lightmap,
 }
 
-#[derive(Clone, Copy)]
-pub struct PointLight {
-    pub position:  Vec3,
-    pub intensity: f32,
-}
-
 macro_rules! plane_funcs {
     () => {
         fn material(&self) -> &Material { &self.material }
@@ -233,14 +251,14 @@ impl XPlane {
     }
 }
 impl YPlane {
-    pub fn new(y: f32, dir: f32, material: Material, colour: Colour, texture: &Texture) -> Self {
+    pub fn new(y: f32, dir: f32, material: Material, colour: Colour, texture: Option<&Texture>) -> Self {
         Self {
             y,
             dir,
             material,
             colour,
             lightmap: Lightmap::default(),
-            texture: texture.clone(),
+            texture: texture.cloned(),
         }
     }
 }
@@ -264,12 +282,13 @@ impl EngineObject for Plane {
 }
 
 impl EngineObject for YPlane {
-    fn colour(&self, _position: Vec3) -> Colour {
-        if self.y > 0.0 {
-            self.colour
-        } else {
-            let (u, v) = self.sample_uv_from_pos(_position);
-            self.texture.sample(u, v)
+    fn colour(&self, position: Vec3) -> Colour {
+        match &self.texture {
+            None => self.colour,
+            Some(texture) => {
+                let (u, v) = self.sample_uv_from_pos(position);
+                texture.sample(u, v)
+            }
         }
     }
     fn sdf(&self, position: Vec3) -> f32 { self.dir * (position.y() - self.y) }
@@ -332,6 +351,7 @@ impl EngineObject for ZPlane {
 
 impl EngineObject for Sphere {
     fn sdf(&self, position: Vec3) -> f32 { (position - self.position).mag() - self.radius }
+    fn bounds(&self) -> Option<Aabb> { Some(Aabb::around(self.position, self.radius)) }
 
     fn colour(&self, _position: Vec3) -> Colour { self.colour }
     fn material(&self) -> &Material { &self.material }
@@ -358,29 +378,531 @@ impl EngineObject for Sphere {
     }
 }
 
-// box
-/*
-let p = Vec3 {
-    x: position.x.abs(),
-    y: position.y.abs(),
-    z: position.z.abs(),
-};
-p.x.max(p.y.max(p.z)) - 1.0 + p.dot(p) * 0.2
-*/
-/*
-let p = position - self.position;
+// the primitives below are centred on `position`, with any long axis along y. their lightmaps wrap around them like
+// a globe, except the torus which is mapped around its ring and tube
 
-let abs_p = Vec3::new(p.x().abs(), p.y().abs(), p.z().abs());
-let s = Vec3::new(1.0, 1.0, 1.0) * 0.7;
-let q = abs_p - s;
+/// Direction from the centre of a lightmapped object towards luxel `(u, v)`
+pub(crate) fn globe_direction(u: usize, v: usize) -> Vec3 {
+    let theta = (((u as f32 / MAP_SIZE as f32) * 2.0) - 1.0) * PI;
+    let phi = ((v as f32 / MAP_SIZE as f32) - 0.5) * PI;
 
-let r = Vec3::new(q.x().max(0.0), q.y().max(0.0), q.z().max(0.0));
+    Vec3::new(theta.sin() * phi.cos(), phi.sin(), -theta.cos() * phi.cos())
+}
 
-r.mag() + (q.x().max(q.y().max(q.z()))).min(0.0)
-*/
+/// Inverse of `globe_direction`, for any offset from the centre
+pub(crate) fn globe_uv(offset: Vec3) -> (f32, f32) {
+    let n = offset.normalized();
+    let u = 0.5 + f32::atan2(n.x(), -n.z()) / TAU;
+    let v = 0.5 + n.y().clamp(-1.0, 1.0).asin() / PI;
+    (u * MAP_SIZE as f32, v * MAP_SIZE as f32)
+}
+
+/// Where the surface is along `direction` from `centre`, which must be inside the object and able to see all of its
+/// surface
+fn surface_point<T: EngineObject + ?Sized>(object: &T, centre: Vec3, direction: Vec3) -> Vec3 {
+    let bounds = object.bounds().unwrap();
+    let (mut inside, mut outside) = (0.0, (bounds.max - bounds.min).mag());
+
+    // bisect, the distance bound of some shapes isn't good enough to march outwards
+    for _ in 0..32 {
+        let t = (inside + outside) * 0.5;
+        if object.sdf(centre + direction * t) < 0.0 {
+            inside = t;
+        } else {
+            outside = t;
+        }
+    }
+    centre + direction * ((inside + outside) * 0.5)
+}
+
+/// Where a ray from the edge of `bounds` towards their centre first meets the surface. The surface doesn't have to
+/// surround the centre, if the ray misses it then it's the closest point the ray passed, moved onto the surface unless
+/// that's somewhere without a normal, like the middle of a hole
+pub(crate) fn outer_surface_point<T: EngineObject + ?Sized>(object: &T, bounds: Aabb, direction: Vec3) -> Vec3 {
+    let centre = bounds.centre();
+    let reach = (bounds.max - bounds.min).mag() * 0.5;
+
+    let mut t = reach;
+    let (mut closest, mut closest_distance) = (centre, f32::INFINITY);
+    for _ in 0..MAX_SURFACE_STEPS {
+        let position = centre + direction * t;
+        let distance = object.sdf(position);
+        if distance < 1e-4 {
+            return position;
+        }
+        if distance < closest_distance {
+            closest = position;
+            closest_distance = distance;
+        }
+        t -= distance;
+        if t < -reach {
+            break;
+        }
+    }
+    let normal = object.calculate_normal(closest);
+    if normal.mag_sqd().is_nan() {
+        return closest;
+    }
+    closest - normal * closest_distance
+}
+
+macro_rules! solid_funcs {
+    () => {
+        fn colour(&self, _position: Vec3) -> Colour { self.colour }
+        plane_funcs!();
+    };
+}
+
+macro_rules! globe_mapped {
+    () => {
+        fn get_sample_pos(&self, u: usize, v: usize) -> Vec3 {
+            surface_point(self, self.position, globe_direction(u, v))
+        }
+        fn sample_uv_from_pos(&self, pos: Vec3) -> (f32, f32) { globe_uv(pos - self.position) }
+    };
+}
+
+/// length of a 2D vector
+fn length2(x: f32, y: f32) -> f32 { (x * x + y * y).sqrt() }
+
+#[derive(Clone, Copy)]
+pub struct Cuboid {
+    pub position:  Vec3,
+    pub half_size: Vec3,
+    pub material:  Material,
+    pub colour:    Colour,
+    pub lightmap:  Lightmap,
+}
+
+/// A box with its edges and corners rounded off, still filling `half_size`
+#[derive(Clone, Copy)]
+pub struct RoundedBox {
+    pub position:  Vec3,
+    pub half_size: Vec3,
+    pub radius:    f32,
+    pub material:  Material,
+    pub colour:    Colour,
+    pub lightmap:  Lightmap,
+}
+
+/// A ring lying flat in the xz plane
+#[derive(Clone, Copy)]
+pub struct Torus {
+    pub position:     Vec3,
+    /// from the centre to the middle of the tube
+    pub major_radius: f32,
+    /// of the tube
+    pub minor_radius: f32,
+    pub material:     Material,
+    pub colour:       Colour,
+    pub lightmap:     Lightmap,
+}
+
+/// Capped cylinder
+#[derive(Clone, Copy)]
+pub struct Cylinder {
+    pub position:    Vec3,
+    pub radius:      f32,
+    pub half_height: f32,
+    pub material:    Material,
+    pub colour:      Colour,
+    pub lightmap:    Lightmap,
+}
+
+/// A line segment with thickness, `half_height` doesn't include the rounded ends
+#[derive(Clone, Copy)]
+pub struct Capsule {
+    pub position:    Vec3,
+    pub radius:      f32,
+    pub half_height: f32,
+    pub material:    Material,
+    pub colour:      Colour,
+    pub lightmap:    Lightmap,
+}
+
+/// Capped cone, `radius` at the bottom and `top_radius` at the top. A top radius of 0 comes to a point
+#[derive(Clone, Copy)]
+pub struct Cone {
+    pub position:    Vec3,
+    pub radius:      f32,
+    pub top_radius:  f32,
+    pub half_height: f32,
+    pub material:    Material,
+    pub colour:      Colour,
+    pub lightmap:    Lightmap,
+}
+
+#[derive(Clone, Copy)]
+pub struct Ellipsoid {
+    pub position: Vec3,
+    pub radii:    Vec3,
+    pub material: Material,
+    pub colour:   Colour,
+    pub lightmap: Lightmap,
+}
+
+/// Hexagonal prism, `radius` is from the centre to the middle of a side
+#[derive(Clone, Copy)]
+pub struct HexPrism {
+    pub position:    Vec3,
+    pub radius:      f32,
+    pub half_height: f32,
+    pub material:    Material,
+    pub colour:      Colour,
+    pub lightmap:    Lightmap,
+}
+
+/// Regular octahedron, `radius` is from the centre to a vertex
+#[derive(Clone, Copy)]
+pub struct Octahedron {
+    pub position: Vec3,
+    pub radius:   f32,
+    pub material: Material,
+    pub colour:   Colour,
+    pub lightmap: Lightmap,
+}
+
+impl EngineObject for Cuboid {
+    fn sdf(&self, position: Vec3) -> f32 {
+        let q = (position - self.position).abs() - self.half_size;
+        q.max(Vec3::default()).mag() + q.max_element().min(0.0)
+    }
+    fn bounds(&self) -> Option<Aabb> {
+        Some(Aabb::new(
+            self.position - self.half_size,
+            self.position + self.half_size,
+        ))
+    }
+
+    solid_funcs!();
+    globe_mapped!();
+}
+
+impl EngineObject for RoundedBox {
+    fn sdf(&self, position: Vec3) -> f32 {
+        let r = self.radius;
+        let q = (position - self.position).abs() - self.half_size + Vec3::new(r, r, r);
+        q.max(Vec3::default()).mag() + q.max_element().min(0.0) - self.radius
+    }
+    fn bounds(&self) -> Option<Aabb> {
+        Some(Aabb::new(
+            self.position - self.half_size,
+            self.position + self.half_size,
+        ))
+    }
+
+    solid_funcs!();
+    globe_mapped!();
+}
+
+impl EngineObject for Torus {
+    fn sdf(&self, position: Vec3) -> f32 {
+        let p = position - self.position;
+        length2(length2(p.x(), p.z()) - self.major_radius, p.y()) - self.minor_radius
+    }
+    fn bounds(&self) -> Option<Aabb> {
+        let outer = self.major_radius + self.minor_radius;
+        let extent = Vec3::new(outer, self.minor_radius, outer);
+        Some(Aabb::new(self.position - extent, self.position + extent))
+    }
+
+    solid_funcs!();
+
+    // u goes around the ring, v around the tube
+    fn get_sample_pos(&self, u: usize, v: usize) -> Vec3 {
+        let ring = (u as f32 / MAP_SIZE as f32) * TAU;
+        let tube = (v as f32 / MAP_SIZE as f32) * TAU;
+
+        let reach = self.major_radius + self.minor_radius * tube.cos();
+        self.position + Vec3::new(ring.cos() * reach, self.minor_radius * tube.sin(), ring.sin() * reach)
+    }
+    fn sample_uv_from_pos(&self, pos: Vec3) -> (f32, f32) {
+        let p = pos - self.position;
+        let ring = f32::atan2(p.z(), p.x()).rem_euclid(TAU);
+        let tube = f32::atan2(p.y(), length2(p.x(), p.z()) - self.major_radius).rem_euclid(TAU);
+        (ring / TAU * MAP_SIZE as f32, tube / TAU * MAP_SIZE as f32)
+    }
+}
 
-impl EngineLight for PointLight {
-    fn get_position(&self) -> Vec3 { self.position }
+impl EngineObject for Cylinder {
+    fn sdf(&self, position: Vec3) -> f32 {
+        let p = position - self.position;
+        let (dx, dy) = (length2(p.x(), p.z()) - self.radius, p.y().abs() - self.half_height);
+        dx.max(dy).min(0.0) + length2(dx.max(0.0), dy.max(0.0))
+    }
+    fn bounds(&self) -> Option<Aabb> {
+        let extent = Vec3::new(self.radius, self.half_height, self.radius);
+        Some(Aabb::new(self.position - extent, self.position + extent))
+    }
+
+    solid_funcs!();
+    globe_mapped!();
+}
 
-    fn get_intensity(&self) -> f32 { self.intensity }
+impl EngineObject for Capsule {
+    fn sdf(&self, position: Vec3) -> f32 {
+        let p = position - self.position;
+        let y = p.y() - p.y().clamp(-self.half_height, self.half_height);
+        Vec3::new(p.x(), y, p.z()).mag() - self.radius
+    }
+    fn bounds(&self) -> Option<Aabb> {
+        let extent = Vec3::new(self.radius, self.half_height + self.radius, self.radius);
+        Some(Aabb::new(self.position - extent, self.position + extent))
+    }
+
+    solid_funcs!();
+    globe_mapped!();
+}
+
+impl EngineObject for Cone {
+    fn sdf(&self, position: Vec3) -> f32 {
+        let p = position - self.position;
+        let (qx, qy) = (length2(p.x(), p.z()), p.y());
+        let (h, r1, r2) = (self.half_height, self.radius, self.top_radius);
+
+        // distance to the caps
+        let cap_radius = if qy < 0.0 { r1 } else { r2 };
+        let (ax, ay) = (qx - qx.min(cap_radius), qy.abs() - h);
+
+        // distance to the slanted side, from its top edge
+        let (kx, ky) = (r2 - r1, 2.0 * h);
+        let t = (((r2 - qx) * kx + (h - qy) * ky) / (kx * kx + ky * ky)).clamp(0.0, 1.0);
+        let (bx, by) = (qx - r2 + kx * t, qy - h + ky * t);
+
+        let sign = if bx < 0.0 && ay < 0.0 { -1.0 } else { 1.0 };
+        sign * (ax * ax + ay * ay).min(bx * bx + by * by).sqrt()
+    }
+    fn bounds(&self) -> Option<Aabb> {
+        let radius = self.radius.max(self.top_radius);
+        let extent = Vec3::new(radius, self.half_height, radius);
+        Some(Aabb::new(self.position - extent, self.position + extent))
+    }
+
+    solid_funcs!();
+    globe_mapped!();
+}
+
+impl EngineObject for Ellipsoid {
+    // not exact, but close near the surface and never overestimates by much
+    fn sdf(&self, position: Vec3) -> f32 {
+        let p = position - self.position;
+        let k0 = p.element_div(self.radii).mag();
+        let k1 = p.element_div(self.radii.element_mul(self.radii)).mag();
+        if k1 == 0.0 {
+            return -self.radii.x().min(self.radii.y()).min(self.radii.z());
+        }
+        k0 * (k0 - 1.0) / k1
+    }
+    fn bounds(&self) -> Option<Aabb> { Some(Aabb::new(self.position - self.radii, self.position + self.radii)) }
+
+    solid_funcs!();
+    globe_mapped!();
+}
+
+impl EngineObject for HexPrism {
+    fn sdf(&self, position: Vec3) -> f32 {
+        // cos 30, sin 30, tan 30
+        const K: (f32, f32, f32) = (-0.866_025_4, 0.5, 0.577_350_3);
+
+        let p = (position - self.position).abs();
+        // fold the hexagon into one of its sides
+        let fold = 2.0 * (K.0 * p.x() + K.1 * p.z()).min(0.0);
+        let (x, z) = (p.x() - fold * K.0, p.z() - fold * K.1);
+
+        let edge = K.2 * self.radius;
+        let side = length2(x - x.clamp(-edge, edge), z - self.radius) * (z - self.radius).signum();
+        let cap = p.y() - self.half_height;
+        side.max(cap).min(0.0) + length2(side.max(0.0), cap.max(0.0))
+    }
+    fn bounds(&self) -> Option<Aabb> {
+        // out to the corners
+        let corner = self.radius * 2.0 / 3f32.sqrt();
+        let extent = Vec3::new(corner, self.half_height, corner);
+        Some(Aabb::new(self.position - extent, self.position + extent))
+    }
+
+    solid_funcs!();
+    globe_mapped!();
+}
+
+impl EngineObject for Octahedron {
+    fn sdf(&self, position: Vec3) -> f32 {
+        let p = (position - self.position).abs();
+        let s = self.radius;
+        let m = p.x() + p.y() + p.z() - s;
+
+        // rotate the axes so the closest face is the same one each time
+        let q = if 3.0 * p.x() < m {
+            p
+        } else if 3.0 * p.y() < m {
+            Vec3::new(p.y(), p.z(), p.x())
+        } else if 3.0 * p.z() < m {
+            Vec3::new(p.z(), p.x(), p.y())
+        } else {
+            return m * 0.577_350_3;
+        };
+
+        let k = (0.5 * (q.z() - q.y() + s)).clamp(0.0, s);
+        Vec3::new(q.x(), q.y() - s + k, q.z() - k).mag()
+    }
+    fn bounds(&self) -> Option<Aabb> { Some(Aabb::around(self.position, self.radius)) }
+
+    solid_funcs!();
+    globe_mapped!();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CENTRE: Vec3 = Vec3::new(1.0, -2.0, 3.0);
+
+    fn assert_close(a: f32, b: f32) { assert!((a - b).abs() < 1e-4, "{} != {}", a, b) }
+
+    /// The distance to `object` at `offset` from `CENTRE`
+    fn distance(object: &dyn EngineObject, x: f32, y: f32, z: f32) -> f32 { object.sdf(CENTRE + Vec3::new(x, y, z)) }
+
+    fn primitives() -> Vec<Box<dyn EngineObject>> {
+        let (position, material, colour, lightmap) =
+            (CENTRE, Material::basic(), Colour::default(), Lightmap::default());
+        vec![
+            Box::new(Cuboid {
+                position,
+                half_size: Vec3::new(1.0, 2.0, 3.0),
+                material,
+                colour,
+                lightmap,
+            }),
+            Box::new(RoundedBox {
+                position,
+                half_size: Vec3::new(1.0, 1.0, 1.0),
+                radius: 0.25,
+                material,
+                colour,
+                lightmap,
+            }),
+            Box::new(Torus {
+                position,
+                major_radius: 2.0,
+                minor_radius: 0.5,
+                material,
+                colour,
+                lightmap,
+            }),
+            Box::new(Cylinder {
+                position,
+                radius: 1.0,
+                half_height: 2.0,
+                material,
+                colour,
+                lightmap,
+            }),
+            Box::new(Capsule {
+                position,
+                radius: 0.5,
+                half_height: 1.0,
+                material,
+                colour,
+                lightmap,
+            }),
+            Box::new(Cone {
+                position,
+                radius: 1.0,
+                top_radius: 0.0,
+                half_height: 1.0,
+                material,
+                colour,
+                lightmap,
+            }),
+            Box::new(Ellipsoid {
+                position,
+                radii: Vec3::new(1.0, 2.0, 3.0),
+                material,
+                colour,
+                lightmap,
+            }),
+            Box::new(HexPrism {
+                position,
+                radius: 1.0,
+                half_height: 1.0,
+                material,
+                colour,
+                lightmap,
+            }),
+            Box::new(Octahedron {
+                position,
+                radius: 1.0,
+                material,
+                colour,
+                lightmap,
+            }),
+        ]
+    }
+
+    #[test]
+    fn known_distances() {
+        let p = primitives();
+
+        // box: a face, a corner and the centre
+        assert_close(distance(&*p[0], 2.0, 0.0, 0.0), 1.0);
+        assert_close(distance(&*p[0], 2.0, 3.0, 4.0), 3f32.sqrt());
+        assert_close(distance(&*p[0], 0.0, 0.0, 0.0), -1.0);
+
+        // rounded box: a face is unchanged, a corner is rounded
+        assert_close(distance(&*p[1], 2.0, 0.0, 0.0), 1.0);
+        assert_close(distance(&*p[1], 2.0, 2.0, 2.0), 1.25 * 3f32.sqrt() - 0.25);
+
+        // torus: the middle of the tube, outside the ring and above the centre
+        assert_close(distance(&*p[2], 2.0, 0.0, 0.0), -0.5);
+        assert_close(distance(&*p[2], 0.0, 0.0, 4.0), 1.5);
+        assert_close(distance(&*p[2], 0.0, 1.0, 0.0), 5f32.sqrt() - 0.5);
+
+        // cylinder: the side, a cap and the rim
+        assert_close(distance(&*p[3], 3.0, 0.0, 0.0), 2.0);
+        assert_close(distance(&*p[3], 0.0, 5.0, 0.0), 3.0);
+        assert_close(distance(&*p[3], 2.0, 3.0, 0.0), 2f32.sqrt());
+
+        // capsule: past a rounded end and beside the middle
+        assert_close(distance(&*p[4], 0.0, 3.0, 0.0), 1.5);
+        assert_close(distance(&*p[4], 0.0, 0.0, -2.0), 1.5);
+
+        // cone: above the tip, the tip itself and below the base
+        assert_close(distance(&*p[5], 0.0, 2.0, 0.0), 1.0);
+        assert_close(distance(&*p[5], 0.0, 1.0, 0.0), 0.0);
+        assert_close(distance(&*p[5], 0.0, -2.0, 0.0), 1.0);
+
+        // ellipsoid: exact along its axes
+        assert_close(distance(&*p[6], 2.0, 0.0, 0.0), 1.0);
+        assert_close(distance(&*p[6], 0.0, 4.0, 0.0), 2.0);
+        assert_close(distance(&*p[6], 0.0, 0.0, 3.0), 0.0);
+
+        // hex prism: the middle of a side and a cap
+        assert_close(distance(&*p[7], 0.0, 0.0, 2.0), 1.0);
+        assert_close(distance(&*p[7], 0.0, 3.0, 0.0), 2.0);
+
+        // octahedron: a vertex, past a vertex and the centre
+        assert_close(distance(&*p[8], 1.0, 0.0, 0.0), 0.0);
+        assert_close(distance(&*p[8], 0.0, -2.0, 0.0), 1.0);
+        assert_close(distance(&*p[8], 0.0, 0.0, 0.0), -1.0 / 3f32.sqrt());
+    }
+
+    #[test]
+    fn bounds_contain_surface() {
+        const STEPS: usize = 24;
+        for object in primitives() {
+            let bounds = object.bounds().unwrap();
+            let size = bounds.max - bounds.min;
+            let corner = bounds.min - size * 0.25;
+
+            // everything inside the object, on a grid reaching past its bounds, is inside the bounds
+            for i in 0..(STEPS + 1).pow(3) {
+                let t = |n: usize| (n % (STEPS + 1)) as f32 / STEPS as f32 * 1.5;
+                let step = Vec3::new(t(i), t(i / (STEPS + 1)), t(i / ((STEPS + 1) * (STEPS + 1))));
+                let position = corner + size.element_mul(step);
+                if object.sdf(position) <= 0.0 {
+                    assert!(bounds.distance(position) < 1e-4, "{} is out of bounds", position);
+                }
+            }
+        }
+    }
 }
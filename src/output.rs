@@ -0,0 +1,187 @@
+//! Writing frames to image files. Finished 8-bit frames go to any format `image` can write, linear frames go to HDR
+//! files before any exposure or tonemapping, for grading and compositing elsewhere. The format is picked from the
+//! file extension.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use image::codecs::hdr::HdrEncoder;
+use image::{ImageResult, Rgb, Rgb32FImage};
+
+use super::colour::Colour;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HdrFormat {
+    /// Radiance RGBE, `.hdr`
+    Radiance,
+    /// portable float map, `.pfm`
+    Pfm,
+    /// scanline OpenEXR with 32-bit float channels, `.exr`
+    OpenExr,
+}
+
+impl HdrFormat {
+    /// The format for a path's extension, if it's an HDR one
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Option<Self> {
+        let extension = path.as_ref().extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "hdr" => Some(HdrFormat::Radiance),
+            "pfm" => Some(HdrFormat::Pfm),
+            "exr" => Some(HdrFormat::OpenExr),
+            _ => None,
+        }
+    }
+}
+
+/// Write a BGRA frame from `Engine::render`, with `pitch` bytes per row
+pub fn save_bgra<P: AsRef<Path>>(path: P, buffer: &[u8], pitch: usize, width: usize, height: usize) -> ImageResult<()> {
+    // image wants tightly packed RGB
+    let rgb: Vec<u8> = buffer
+        .chunks(pitch)
+        .take(height)
+        .flat_map(|row| row[..width * 4].chunks_exact(4).flat_map(|p| [p[2], p[1], p[0]]))
+        .collect();
+    image::save_buffer(path, &rgb, width as u32, height as u32, image::ColorType::Rgb8)
+}
+
+/// Write rows of linear colour, top row first, in `format`
+pub fn save_hdr<P: AsRef<Path>>(path: P, frame: &[Vec<Colour>], format: HdrFormat) -> ImageResult<()> {
+    let (width, height) = (frame.first().map_or(0, |row| row.len()), frame.len());
+    let pixels = frame.iter().flatten();
+
+    match format {
+        HdrFormat::Radiance => {
+            let pixels: Vec<Rgb<f32>> = pixels.map(|c| Rgb([c.x(), c.y(), c.z()])).collect();
+            HdrEncoder::new(BufWriter::new(File::create(path)?)).encode(&pixels, width, height)
+        }
+        HdrFormat::OpenExr => {
+            let data = pixels.flat_map(|c| [c.x(), c.y(), c.z()]).collect();
+            // the buffer is the right size, so this can't fail
+            let image = Rgb32FImage::from_raw(width as u32, height as u32, data).unwrap();
+            image.save_with_format(path, image::ImageFormat::OpenExr)
+        }
+        HdrFormat::Pfm => {
+            let mut file = BufWriter::new(File::create(path)?);
+            // a negative scale means little endian
+            write!(file, "PF\n{} {}\n-1.0\n", width, height)?;
+            // rows go bottom to top
+            for row in frame.iter().rev() {
+                for c in row {
+                    for channel in [c.x(), c.y(), c.z()] {
+                        file.write_all(&channel.to_le_bytes())?;
+                    }
+                }
+            }
+            file.flush()?;
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::codecs::hdr::HdrDecoder;
+    use std::fs;
+    use std::io::BufReader;
+    use std::path::PathBuf;
+
+    /// A 3x2 frame with a different colour in every pixel
+    fn frame() -> Vec<Vec<Colour>> {
+        (0..2)
+            .map(|y| {
+                (0..3)
+                    .map(|x| Colour::new(x as f32 * 0.5, y as f32 * 4.0, 0.25))
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn temp_path(name: &str) -> PathBuf { std::env::temp_dir().join(format!("{}-{}", std::process::id(), name)) }
+
+    #[test]
+    fn formats_from_extensions() {
+        assert_eq!(HdrFormat::from_path("render.hdr"), Some(HdrFormat::Radiance));
+        assert_eq!(HdrFormat::from_path("out/render.PFM"), Some(HdrFormat::Pfm));
+        assert_eq!(HdrFormat::from_path("render.exr"), Some(HdrFormat::OpenExr));
+        assert_eq!(HdrFormat::from_path("render.png"), None);
+        assert_eq!(HdrFormat::from_path("render"), None);
+    }
+
+    #[test]
+    fn bgra_to_png() {
+        // two rows of two pixels, padded to 12 bytes a row
+        let buffer = [
+            [0, 0, 255, 0, 0, 255, 0, 0, 9, 9, 9, 9],
+            [255, 0, 0, 0, 10, 20, 30, 0, 9, 9, 9, 9],
+        ]
+        .concat();
+        let path = temp_path("frame.png");
+        save_bgra(&path, &buffer, 12, 2, 2).unwrap();
+        let image = image::open(&path).unwrap().into_rgb8();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(image.dimensions(), (2, 2));
+        let pixels: Vec<[u8; 3]> = image.pixels().map(|p| p.0).collect();
+        assert_eq!(pixels, [[255, 0, 0], [0, 255, 0], [0, 0, 255], [30, 20, 10]]);
+    }
+
+    #[test]
+    fn pfm_layout() {
+        let path = temp_path("frame.pfm");
+        save_hdr(&path, &frame(), HdrFormat::Pfm).unwrap();
+        let bytes = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let header = b"PF\n3 2\n-1.0\n";
+        assert_eq!(&bytes[..header.len()], header);
+        let floats: Vec<f32> = bytes[header.len()..]
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect();
+        assert_eq!(floats.len(), 3 * 2 * 3);
+        // the bottom row comes first
+        assert_eq!(&floats[..6], &[0.0, 4.0, 0.25, 0.5, 4.0, 0.25]);
+        assert_eq!(&floats[9..12], &[0.0, 0.0, 0.25]);
+    }
+
+    /// Every pixel read back from `path` matches the frame to within `tolerance`, relative to its value
+    fn assert_matches(path: &Path, pixels: &[Rgb<f32>], tolerance: f32) {
+        let written = frame().into_iter().flatten().collect::<Vec<_>>();
+        assert_eq!(pixels.len(), written.len(), "{}", path.display());
+        for (&Rgb([r, g, b]), colour) in pixels.iter().zip(written) {
+            for (read, written) in [(r, colour.x()), (g, colour.y()), (b, colour.z())] {
+                assert!(
+                    (read - written).abs() <= tolerance * written,
+                    "{}: {} != {}",
+                    path.display(),
+                    read,
+                    written
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn radiance_round_trip() {
+        let path = temp_path("frame.hdr");
+        save_hdr(&path, &frame(), HdrFormat::Radiance).unwrap();
+        let decoder = HdrDecoder::new(BufReader::new(File::open(&path).unwrap())).unwrap();
+        assert_eq!((decoder.metadata().width, decoder.metadata().height), (3, 2));
+        let pixels = decoder.read_image_hdr().unwrap();
+        fs::remove_file(&path).unwrap();
+        // RGBE shares one exponent between the channels
+        assert_matches(&path, &pixels, 0.01);
+    }
+
+    #[test]
+    fn exr_round_trip() {
+        let path = temp_path("frame.exr");
+        save_hdr(&path, &frame(), HdrFormat::OpenExr).unwrap();
+        let image = image::open(&path).unwrap().into_rgb32f();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(image.dimensions(), (3, 2));
+        assert_matches(&path, &image.pixels().copied().collect::<Vec<_>>(), 0.0);
+    }
+}
@@ -0,0 +1,667 @@
+//! Post-processing. An ordered list of passes run over the whole frame once it's been accumulated. The frame starts
+//! out as linear HDR colour, and the output transfer pass turns it into display values, so passes that expect one or
+//! the other need to go on the right side of it.
+
+use super::colour::{agx, hable, linear_to_srgb, luminance, reinhard, ACESFilm, Colour};
+use super::fxaa::Fxaa;
+
+use rayon::prelude::*;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::Instant;
+
+const HISTOGRAM_BINS: usize = 64;
+// range of log2 luminance the histogram covers, anything outside goes in the end bins
+const HISTOGRAM_MIN_EV: f32 = -12.0;
+const HISTOGRAM_MAX_EV: f32 = 6.0;
+
+/// Rows of colours, top row first
+pub type Frame = Vec<Vec<Colour>>;
+
+pub trait PostPass: Send + Sync {
+    /// Short name used in scene files and when toggling the pass
+    fn name(&self) -> &'static str;
+
+    fn apply(&self, frame: &mut Frame);
+}
+
+pub type PassRef = Box<dyn PostPass>;
+
+pub struct Stage {
+    pub pass:    PassRef,
+    pub enabled: bool,
+}
+
+/// The passes to run, in order
+#[derive(Default)]
+pub struct PostProcess {
+    pub stages: Vec<Stage>,
+}
+
+impl PostProcess {
+    /// Exposure, ACES and sRGB, with the other passes there but turned off so they can be toggled on
+    pub fn standard() -> Self {
+        let mut post = Self::default();
+        post.push(AutoExposure::default(), false);
+        post.push(Exposure(1.0), true);
+        post.push(Bloom::default(), false);
+        post.push(Tonemap(Tonemapper::Aces), true);
+        post.push(ColourGrade::default(), false);
+        post.push(Vignette::default(), false);
+        post.push(Sharpen::default(), false);
+        post.push(OutputTransfer(Transfer::Srgb), true);
+        post.push(Fxaa::default(), false);
+        post
+    }
+
+    pub fn push<P: PostPass + 'static>(&mut self, pass: P, enabled: bool) {
+        self.stages.push(Stage {
+            pass: Box::new(pass),
+            enabled,
+        });
+    }
+
+    /// Flip the stage at `index`, returning it if there is one
+    pub fn toggle(&mut self, index: usize) -> Option<&Stage> {
+        let stage = self.stages.get_mut(index)?;
+        stage.enabled = !stage.enabled;
+        Some(stage)
+    }
+
+    /// Flip the first stage called `name`, returning whether it's now enabled
+    pub fn toggle_named(&mut self, name: &str) -> Option<bool> {
+        let index = self.stages.iter().position(|stage| stage.pass.name() == name)?;
+        self.toggle(index).map(|stage| stage.enabled)
+    }
+
+    pub fn apply(&self, frame: &mut Frame) {
+        if frame.is_empty() || frame[0].is_empty() {
+            return;
+        }
+        for stage in self.stages.iter().filter(|stage| stage.enabled) {
+            stage.pass.apply(frame);
+        }
+    }
+}
+
+/// Run `f` on every pixel with its position
+fn map_pixels<F: Fn(usize, usize, Colour) -> Colour + Sync>(frame: &mut Frame, f: F) {
+    frame.par_iter_mut().enumerate().for_each(|(y, row)| {
+        for (x, colour) in row.iter_mut().enumerate() {
+            *colour = f(x, y, *colour);
+        }
+    });
+}
+
+/// Scales the linear colour
+#[derive(Clone, Copy, Debug)]
+pub struct Exposure(pub f32);
+
+impl PostPass for Exposure {
+    fn name(&self) -> &'static str { "exposure" }
+
+    fn apply(&self, frame: &mut Frame) { map_pixels(frame, |_, _, colour| colour * self.0) }
+}
+
+/// Picks the exposure from a histogram of the frame's luminance, so the average of what's on screen lands on `key`.
+/// The exposure moves towards that over time rather than jumping, like eyes adjusting
+#[derive(Debug)]
+pub struct AutoExposure {
+    /// luminance the average is scaled to, 0.18 is mid grey
+    pub key:          f32,
+    /// fraction of the darkest pixels left out of the average
+    pub low:          f32,
+    /// fraction of pixels, from the darkest, after which the brightest are left out
+    pub high:         f32,
+    /// rate the exposure adapts at, per second
+    pub speed:        f32,
+    pub min_exposure: f32,
+    pub max_exposure: f32,
+    /// log2 of the current exposure, and when it was last updated
+    adapted:          Mutex<Option<(f32, Instant)>>,
+}
+
+impl Default for AutoExposure {
+    fn default() -> Self {
+        Self {
+            key:          0.18,
+            low:          0.5,
+            high:         0.95,
+            speed:        1.5,
+            min_exposure: 0.125,
+            max_exposure: 16.0,
+            adapted:      Mutex::new(None),
+        }
+    }
+}
+
+fn bin_ev(bin: usize) -> f32 {
+    HISTOGRAM_MIN_EV + (bin as f32 + 0.5) / HISTOGRAM_BINS as f32 * (HISTOGRAM_MAX_EV - HISTOGRAM_MIN_EV)
+}
+
+fn luminance_histogram(frame: &Frame) -> [u32; HISTOGRAM_BINS] {
+    frame
+        .par_iter()
+        .map(|row| {
+            let mut bins = [0u32; HISTOGRAM_BINS];
+            for &colour in row {
+                let ev = luminance(colour).max(f32::MIN_POSITIVE).log2();
+                let t = ((ev - HISTOGRAM_MIN_EV) / (HISTOGRAM_MAX_EV - HISTOGRAM_MIN_EV)).clamp(0.0, 1.0);
+                bins[((t * HISTOGRAM_BINS as f32) as usize).min(HISTOGRAM_BINS - 1)] += 1;
+            }
+            bins
+        })
+        .reduce(
+            || [0; HISTOGRAM_BINS],
+            |mut a, b| {
+                a.iter_mut().zip(b).for_each(|(a, b)| *a += b);
+                a
+            },
+        )
+}
+
+impl AutoExposure {
+    /// log2 of the exposure that puts the frame's average on `key`
+    fn target_ev(&self, frame: &Frame) -> f32 {
+        let bins = luminance_histogram(frame);
+        let total = bins.iter().sum::<u32>() as f32;
+        let mut skip = total * self.low;
+        let mut remaining = total * (self.high - self.low).max(0.0);
+
+        // average log luminance between the two percentiles
+        let (mut sum, mut weight) = (0.0, 0.0);
+        for (bin, &count) in bins.iter().enumerate() {
+            let mut count = count as f32;
+            let skipped = count.min(skip);
+            skip -= skipped;
+            count -= skipped;
+            let used = count.min(remaining);
+            remaining -= used;
+            sum += used * bin_ev(bin);
+            weight += used;
+        }
+        let average_ev = if weight > 0.0 { sum / weight } else { self.key.log2() };
+
+        (self.key.log2() - average_ev).clamp(self.min_exposure.log2(), self.max_exposure.log2())
+    }
+
+    /// The exposure used on the last frame, if there's been one
+    pub fn exposure(&self) -> Option<f32> { self.adapted.lock().unwrap().map(|(ev, _)| ev.exp2()) }
+}
+
+impl PostPass for AutoExposure {
+    fn name(&self) -> &'static str { "auto_exposure" }
+
+    fn apply(&self, frame: &mut Frame) {
+        let target = self.target_ev(frame);
+        let now = Instant::now();
+        let mut adapted = self.adapted.lock().unwrap();
+
+        // the first frame goes straight to the target
+        let ev = match *adapted {
+            Some((ev, last)) => {
+                let blend = 1.0 - (-self.speed * now.duration_since(last).as_secs_f32()).exp();
+                ev + (target - ev) * blend
+            }
+            None => target,
+        };
+        *adapted = Some((ev, now));
+
+        let exposure = ev.exp2();
+        map_pixels(frame, |_, _, colour| colour * exposure)
+    }
+}
+
+/// Curves mapping HDR colour into 0 to 1
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Tonemapper {
+    Aces,
+    /// the original Reinhard curve, only reaches white at infinity
+    Reinhard,
+    /// Reinhard with the luminance that maps to white
+    ReinhardExtended(f32),
+    /// Hable's filmic curve from Uncharted 2
+    Hable,
+    AgX,
+    /// no curve, anything over 1 is clipped
+    Clamp,
+}
+
+impl Tonemapper {
+    pub fn map(self, colour: Colour) -> Colour {
+        match self {
+            Tonemapper::Aces => ACESFilm(colour),
+            Tonemapper::Reinhard => reinhard(colour, f32::INFINITY),
+            Tonemapper::ReinhardExtended(white) => reinhard(colour, white),
+            Tonemapper::Hable => hable(colour),
+            Tonemapper::AgX => agx(colour),
+            Tonemapper::Clamp => colour.min(Colour::new(1.0, 1.0, 1.0)),
+        }
+    }
+}
+
+impl FromStr for Tonemapper {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "aces" => Ok(Tonemapper::Aces),
+            "reinhard" => Ok(Tonemapper::Reinhard),
+            "hable" | "uncharted2" => Ok(Tonemapper::Hable),
+            "agx" => Ok(Tonemapper::AgX),
+            "clamp" => Ok(Tonemapper::Clamp),
+            _ => Err(format!(
+                "unknown tonemapper `{}`, expected aces, reinhard, hable, agx or clamp",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Tonemap(pub Tonemapper);
+
+impl PostPass for Tonemap {
+    fn name(&self) -> &'static str { "tonemap" }
+
+    fn apply(&self, frame: &mut Frame) { map_pixels(frame, |_, _, colour| self.0.map(colour)) }
+}
+
+/// Curve from linear colour to the values sent to the display
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Transfer {
+    Linear,
+    /// the piecewise sRGB curve
+    Srgb,
+    /// a plain power curve
+    Gamma(f32),
+}
+
+impl Transfer {
+    pub fn encode(self, colour: Colour) -> Colour {
+        match self {
+            Transfer::Linear => colour,
+            Transfer::Srgb => Colour::new(
+                linear_to_srgb(colour.x()),
+                linear_to_srgb(colour.y()),
+                linear_to_srgb(colour.z()),
+            ),
+            // the common case, and much cheaper than powf
+            Transfer::Gamma(2.0) => colour.sqrt(),
+            Transfer::Gamma(gamma) => {
+                let gamma = gamma.recip();
+                Colour::new(colour.x().powf(gamma), colour.y().powf(gamma), colour.z().powf(gamma))
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct OutputTransfer(pub Transfer);
+
+impl PostPass for OutputTransfer {
+    fn name(&self) -> &'static str { "output" }
+
+    fn apply(&self, frame: &mut Frame) {
+        map_pixels(frame, |_, _, colour| self.0.encode(colour.max(Colour::default())))
+    }
+}
+
+/// Simple grade for after tonemapping
+#[derive(Clone, Copy, Debug)]
+pub struct ColourGrade {
+    /// multiplies the colour, for white balance or a colour cast
+    pub tint:       Colour,
+    /// 0 is greyscale, 1 leaves the colour alone
+    pub saturation: f32,
+    /// power curve around mid grey, 1 leaves the colour alone
+    pub contrast:   f32,
+}
+
+impl Default for ColourGrade {
+    fn default() -> Self {
+        Self {
+            tint:       Colour::new(1.0, 1.0, 1.0),
+            saturation: 1.0,
+            contrast:   1.0,
+        }
+    }
+}
+
+impl PostPass for ColourGrade {
+    fn name(&self) -> &'static str { "grade" }
+
+    fn apply(&self, frame: &mut Frame) {
+        const MID_GREY: f32 = 0.18;
+        let contrast = |c: f32| MID_GREY * (c.max(0.0) / MID_GREY).powf(self.contrast);
+
+        map_pixels(frame, |_, _, colour| {
+            let colour = colour.element_mul(self.tint);
+            let grey = luminance(colour);
+            let colour = Colour::new(grey, grey, grey) * (1.0 - self.saturation) + colour * self.saturation;
+            Colour::new(contrast(colour.x()), contrast(colour.y()), contrast(colour.z()))
+        })
+    }
+}
+
+/// Glow around bright areas: everything over a threshold is blurred and added back on. Goes before tonemapping
+#[derive(Clone, Copy, Debug)]
+pub struct Bloom {
+    /// luminance above which pixels glow
+    pub threshold: f32,
+    pub intensity: f32,
+    /// standard deviation of the blur, in pixels
+    pub radius:    f32,
+}
+
+impl Default for Bloom {
+    fn default() -> Self {
+        Self {
+            threshold: 1.0,
+            intensity: 0.3,
+            radius:    8.0,
+        }
+    }
+}
+
+/// Separable gaussian blur, clamped at the edges
+fn gaussian_blur(frame: &Frame, sigma: f32) -> Frame {
+    let (width, height) = (frame[0].len(), frame.len());
+    let reach = (sigma * 3.0).ceil() as isize;
+    let mut kernel: Vec<f32> = (-reach..=reach)
+        .map(|i| (-(i * i) as f32 / (2.0 * sigma * sigma)).exp())
+        .collect();
+    let total: f32 = kernel.iter().sum();
+    kernel.iter_mut().for_each(|k| *k /= total);
+
+    let blur_at = |get: &dyn Fn(usize) -> Colour, centre: usize, len: usize| {
+        let mut sum = Colour::default();
+        for (i, k) in kernel.iter().enumerate() {
+            let at = (centre as isize + i as isize - reach).clamp(0, len as isize - 1) as usize;
+            sum += get(at) * *k;
+        }
+        sum
+    };
+
+    let horizontal: Frame = frame
+        .par_iter()
+        .map(|row| (0..width).map(|x| blur_at(&|at| row[at], x, width)).collect())
+        .collect();
+    (0..height)
+        .into_par_iter()
+        .map(|y| {
+            (0..width)
+                .map(|x| blur_at(&|at| horizontal[at][x], y, height))
+                .collect()
+        })
+        .collect()
+}
+
+impl PostPass for Bloom {
+    fn name(&self) -> &'static str { "bloom" }
+
+    fn apply(&self, frame: &mut Frame) {
+        let bright: Frame = frame
+            .par_iter()
+            .map(|row| {
+                row.iter()
+                    .map(|&colour| {
+                        let luma = luminance(colour);
+                        // keep the hue, only the part over the threshold glows
+                        if luma > self.threshold {
+                            colour * ((luma - self.threshold) / luma)
+                        } else {
+                            Colour::default()
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+        let glow = gaussian_blur(&bright, self.radius.max(0.5));
+        map_pixels(frame, |x, y, colour| colour + glow[y][x] * self.intensity);
+    }
+}
+
+/// Darkens towards the corners
+#[derive(Clone, Copy, Debug)]
+pub struct Vignette {
+    /// how much darker the corners get, 0 to 1
+    pub strength: f32,
+    /// where the darkening starts, as a fraction of the distance from the centre to a corner
+    pub radius:   f32,
+}
+
+impl Default for Vignette {
+    fn default() -> Self {
+        Self {
+            strength: 0.4,
+            radius:   0.5,
+        }
+    }
+}
+
+impl PostPass for Vignette {
+    fn name(&self) -> &'static str { "vignette" }
+
+    fn apply(&self, frame: &mut Frame) {
+        let (width, height) = (frame[0].len() as f32, frame.len() as f32);
+        let half_diagonal = 0.5 * (width * width + height * height).sqrt();
+
+        map_pixels(frame, |x, y, colour| {
+            let (dx, dy) = (x as f32 + 0.5 - width * 0.5, y as f32 + 0.5 - height * 0.5);
+            let distance = (dx * dx + dy * dy).sqrt() / half_diagonal;
+            let t = ((distance - self.radius) / (1.0 - self.radius).max(1e-3)).clamp(0.0, 1.0);
+            // smoothstep
+            colour * (1.0 - self.strength * (3.0 * t.powi(2) - 2.0 * t.powi(3)))
+        })
+    }
+}
+
+/// Unsharp mask against the four neighbouring pixels
+#[derive(Clone, Copy, Debug)]
+pub struct Sharpen {
+    pub amount: f32,
+}
+
+impl Default for Sharpen {
+    fn default() -> Self { Self { amount: 0.5 } }
+}
+
+impl PostPass for Sharpen {
+    fn name(&self) -> &'static str { "sharpen" }
+
+    fn apply(&self, frame: &mut Frame) {
+        let source = frame.clone();
+        let (width, height) = (source[0].len(), source.len());
+
+        map_pixels(frame, |x, y, colour| {
+            let neighbours = source[y.saturating_sub(1)][x]
+                + source[(y + 1).min(height - 1)][x]
+                + source[y][x.saturating_sub(1)]
+                + source[y][(x + 1).min(width - 1)];
+            (colour + (colour - neighbours * 0.25) * self.amount).max(Colour::default())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(width: usize, height: usize, colour: Colour) -> Frame { vec![vec![colour; width]; height] }
+
+    fn grey(v: f32) -> Colour { Colour::new(v, v, v) }
+
+    fn assert_close(a: Colour, b: Colour) { assert!((a - b).mag() < 1e-5, "{} != {}", a, b) }
+
+    #[test]
+    fn standard_pipeline() {
+        let mut post = PostProcess::standard();
+        let names: Vec<_> = post.stages.iter().map(|stage| stage.pass.name()).collect();
+        assert_eq!(
+            names,
+            [
+                "auto_exposure",
+                "exposure",
+                "bloom",
+                "tonemap",
+                "grade",
+                "vignette",
+                "sharpen",
+                "output",
+                "fxaa"
+            ]
+        );
+
+        assert_eq!(post.toggle_named("bloom"), Some(true));
+        assert_eq!(post.toggle_named("bloom"), Some(false));
+        assert_eq!(post.toggle_named("lens_flare"), None);
+        assert!(post.toggle(9).is_none());
+
+        // nothing to do, but it mustn't fall over
+        post.apply(&mut Vec::new());
+        post.apply(&mut vec![Vec::new()]);
+    }
+
+    #[test]
+    fn passes_run_in_order() {
+        let mut post = PostProcess::default();
+        post.push(Exposure(4.0), true);
+        post.push(OutputTransfer(Transfer::Gamma(2.0)), true);
+        post.push(Exposure(10.0), false);
+
+        let mut image = frame(2, 2, grey(0.25));
+        post.apply(&mut image);
+        assert_close(image[1][1], grey(1.0));
+
+        assert_close(Transfer::Gamma(2.2).encode(grey(0.5)), grey(0.5f32.powf(1.0 / 2.2)));
+        assert_close(Transfer::Linear.encode(grey(0.5)), grey(0.5));
+        assert_close(Transfer::Srgb.encode(grey(0.214_041_14)), grey(0.5));
+    }
+
+    #[test]
+    fn grade() {
+        let colour = Colour::new(0.8, 0.3, 0.1);
+        let mut image = frame(1, 1, colour);
+        ColourGrade::default().apply(&mut image);
+        assert_close(image[0][0], colour);
+
+        let greyscale = ColourGrade {
+            saturation: 0.0,
+            ..ColourGrade::default()
+        };
+        let mut image = frame(1, 1, colour);
+        greyscale.apply(&mut image);
+        assert_close(image[0][0], grey(luminance(colour)));
+    }
+
+    #[test]
+    fn bloom_only_spreads_bright_pixels() {
+        let bloom = Bloom {
+            radius: 1.5,
+            ..Bloom::default()
+        };
+        let mut dim = frame(15, 15, grey(0.5));
+        bloom.apply(&mut dim);
+        assert_close(dim[7][7], grey(0.5));
+
+        let mut image = frame(15, 15, Colour::default());
+        image[7][7] = grey(11.0);
+        bloom.apply(&mut image);
+        // the glow reaches the neighbours, and adds up to the part over the threshold
+        assert!(image[7][8].x() > 0.0 && image[9][6].x() > 0.0);
+        let total: f32 = image.iter().flatten().map(|colour| colour.x()).sum();
+        assert!((total - (11.0 + 10.0 * bloom.intensity)).abs() < 1e-3, "{}", total);
+    }
+
+    #[test]
+    fn vignette_darkens_corners() {
+        let mut image = frame(20, 10, grey(1.0));
+        Vignette {
+            strength: 0.5,
+            radius:   0.5,
+        }
+        .apply(&mut image);
+        assert_close(image[5][10], grey(1.0));
+        assert!(image[0][0].x() < 0.6 && image[0][0].x() >= 0.5);
+    }
+
+    #[test]
+    fn sharpen() {
+        let mut flat = frame(4, 4, grey(0.5));
+        Sharpen::default().apply(&mut flat);
+        assert_close(flat[2][2], grey(0.5));
+
+        // a lone bright pixel gets brighter, and its neighbours darker
+        let mut image = frame(3, 3, grey(0.5));
+        image[1][1] = grey(1.0);
+        Sharpen { amount: 1.0 }.apply(&mut image);
+        assert_close(image[1][1], grey(1.5));
+        assert!(image[0][1].x() < 0.5);
+    }
+
+    #[test]
+    fn tonemappers() {
+        let tonemappers = [
+            Tonemapper::Aces,
+            Tonemapper::Reinhard,
+            Tonemapper::ReinhardExtended(4.0),
+            Tonemapper::Hable,
+            Tonemapper::AgX,
+            Tonemapper::Clamp,
+        ];
+        for &tonemapper in &tonemappers {
+            assert!(tonemapper.map(grey(0.0)).x().abs() < 1e-3, "{:?}", tonemapper);
+
+            // brighter in is never darker out
+            let mut last = 0.0;
+            for i in 1..200 {
+                let mapped = tonemapper.map(grey(i as f32 * 0.05)).x();
+                assert!(mapped >= last - 1e-6, "{:?} at {}", tonemapper, i);
+                last = mapped;
+            }
+        }
+
+        // nothing goes past white, though the ACES fit levels off just above it
+        for &(tonemapper, white) in &[
+            (Tonemapper::Reinhard, 1.0),
+            (Tonemapper::AgX, 1.0),
+            (Tonemapper::Clamp, 1.0),
+            (Tonemapper::Aces, 2.51 / 2.43),
+        ] {
+            assert!(tonemapper.map(grey(1e4)).x() <= white + 1e-3, "{:?}", tonemapper);
+        }
+        assert_close(Tonemapper::Hable.map(grey(5.6)), grey(1.0));
+        assert_close(Tonemapper::Reinhard.map(grey(1.0)), grey(0.5));
+        assert_close(Tonemapper::ReinhardExtended(4.0).map(grey(4.0)), grey(1.0));
+        assert_close(
+            Tonemapper::Clamp.map(Colour::new(2.0, 0.5, 1.0)),
+            Colour::new(1.0, 0.5, 1.0),
+        );
+        // Reinhard works on luminance, so the hue is kept
+        let mapped = Tonemapper::Reinhard.map(Colour::new(4.0, 2.0, 1.0));
+        assert!((mapped.x() / mapped.y() - 2.0).abs() < 1e-5 && (mapped.y() / mapped.z() - 2.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn auto_exposure() {
+        // the first frame goes straight to the exposure that brings it to mid grey
+        let auto = AutoExposure::default();
+        let mut image = frame(8, 8, grey(0.045));
+        auto.apply(&mut image);
+        let exposure = auto.exposure().unwrap();
+        assert!((exposure.log2() - 2.0).abs() < 0.15, "{}", exposure);
+        assert_close(image[3][3], grey(0.045 * exposure));
+
+        // only the pixels between the percentiles count, so a few bright ones don't darken everything
+        let auto = AutoExposure::default();
+        let mut image = frame(10, 10, grey(0.045));
+        image[0][0] = grey(1000.0);
+        auto.apply(&mut image);
+        assert!((auto.exposure().unwrap().log2() - 2.0).abs() < 0.15);
+
+        let auto = AutoExposure::default();
+        auto.apply(&mut frame(4, 4, Colour::default()));
+        assert_eq!(auto.exposure(), Some(auto.max_exposure));
+    }
+}
@@ -2,9 +2,9 @@ use std::f32::consts::PI;
 
 use super::vector::Vec3;
 use crate::{
-    engine::{ObjectRef, SMALL_DISTANCE},
-    objects::{EngineLight, PointLight},
-    ray::Ray,
+    bvh::Bvh,
+    engine::{LightRef, ObjectRef, SMALL_DISTANCE},
+    ray::{MarchResult, MarchSettings, Ray},
 };
 
 use Vec3 as Colour;
@@ -48,14 +48,11 @@ pub struct Sample {
     pub obj_eng_index:   usize,
 }
 
-/// Compute the direct lighting on a lightmap
+/// Compute the direct lighting from every light on a lightmap
 pub fn compute_direct_lighting(
-    light: &PointLight, obj_indexes: &Vec<usize>, objects: &Vec<ObjectRef>,
+    lights: &[LightRef], obj_indexes: &Vec<usize>, objects: &Vec<ObjectRef>, bvh: &Bvh, march: &MarchSettings,
     point_cloud: &Vec<[[Vec3; MAP_SIZE]; MAP_SIZE]>, colour_cloud: &Vec<[[Colour; MAP_SIZE]; MAP_SIZE]>,
 ) -> (Vec<Lightmap>, Vec<Lightmap>) {
-    let light_pos = light.get_position();
-    let light_intensity = light.get_intensity();
-
     let mut emissive_maps: Vec<Lightmap> = Vec::new();
     emissive_maps.reserve(obj_indexes.len());
     let mut new_lightmaps: Vec<Lightmap> = Vec::new();
@@ -65,32 +62,34 @@ pub fn compute_direct_lighting(
         let object = &objects[obj_index];
         let mut obj_lightmap = Lightmap::default();
         let mut emissive_map = Lightmap::default();
-        let emissivity = object.material().emissive;
 
         for x in 0..MAP_SIZE {
             for y in 0..MAP_SIZE {
                 let origin = point_cloud[cloud_index][x][y];
+                let emissivity = object.material_at(origin).emissive;
                 let colour = colour_cloud[cloud_index][x][y];
+                let n = object.calculate_normal(origin);
+                let mut diffuse = Colour::default();
 
-                let vector_to_light = light_pos - origin;
-                let distance_to_light = vector_to_light.mag();
-                let vector_to_light = vector_to_light / distance_to_light;
+                for light in lights {
+                    let light_sample = light.sample(origin);
 
-                let mut shadow_ray = Ray {
-                    position:  origin,
-                    direction: vector_to_light,
-                };
-                let _ = shadow_ray.march(&objects, Some(obj_index));
+                    let mut shadow_ray = Ray {
+                        position:  origin,
+                        direction: light_sample.direction,
+                    };
+                    let hit = shadow_ray.march(objects, bvh, march, Some(obj_index));
 
-                // if we don't make it to the light, something is in the way... so ignore
-                if (shadow_ray.position - origin).mag() < (distance_to_light + 3.0 * SMALL_DISTANCE) {
-                    continue;
-                }
+                    // if we hit something before reaching the light, something is in the way... so ignore
+                    let hit_distance = (shadow_ray.position - origin).mag();
+                    if hit != MarchResult::Miss && hit_distance < light_sample.distance - 3.0 * SMALL_DISTANCE {
+                        continue;
+                    }
 
-                let n = object.calculate_normal(origin);
-                let diffuse = n.dot(vector_to_light).max(0.0) * light_intensity / (distance_to_light).powi(2);
+                    diffuse += light_sample.intensity * n.dot(light_sample.direction).max(0.0);
+                }
 
-                emissive_map.sample_map[x][y] = colour * (diffuse + emissivity);
+                emissive_map.sample_map[x][y] = colour.element_mul(diffuse) + colour * emissivity;
                 obj_lightmap.sample_map[x][y] = colour * emissivity; // an emissive object is directly lit by it
             }
         }
@@ -101,7 +100,7 @@ pub fn compute_direct_lighting(
 }
 
 pub fn compute_patch_radiosity(
-    objects: &Vec<ObjectRef>, point_cloud: &Vec<[[Vec3; MAP_SIZE]; MAP_SIZE]>,
+    objects: &Vec<ObjectRef>, bvh: &Bvh, march: &MarchSettings, point_cloud: &Vec<[[Vec3; MAP_SIZE]; MAP_SIZE]>,
     normal_cloud: &Vec<[[Vec3; MAP_SIZE]; MAP_SIZE]>, emissive_maps: &Vec<Lightmap>, sample: Sample,
 ) -> Colour {
     let mut incident: Colour = Colour::new(0.0, 0.0, 0.0);
@@ -131,7 +130,7 @@ pub fn compute_patch_radiosity(
                     position:  light_source + (3.0 * SMALL_DISTANCE * -vector_to_light),
                     direction: -vector_to_light,
                 };
-                let hit = shadow_ray.radiosity_march(objects, None);
+                let hit = shadow_ray.radiosity_march(objects, bvh, march, None).hit();
                 if hit.is_some() {
                     // if the collision was super close, ignore it. it's probably an unlucky sample position
                     if hit.unwrap() != sample.obj_eng_index {
@@ -157,9 +156,10 @@ pub fn compute_patch_radiosity(
 }
 
 pub fn compute_object_radiosity(
-    objects: &Vec<ObjectRef>, object: &ObjectRef, obj_cloud_index: usize, obj_eng_index: usize,
-    point_cloud: &Vec<[[Vec3; MAP_SIZE]; MAP_SIZE]>, colour_cloud: &Vec<[[Colour; MAP_SIZE]; MAP_SIZE]>,
-    normal_cloud: &Vec<[[Vec3; MAP_SIZE]; MAP_SIZE]>, emissive_maps: &Vec<Lightmap>,
+    objects: &Vec<ObjectRef>, bvh: &Bvh, march: &MarchSettings, object: &ObjectRef, obj_cloud_index: usize,
+    obj_eng_index: usize, point_cloud: &Vec<[[Vec3; MAP_SIZE]; MAP_SIZE]>,
+    colour_cloud: &Vec<[[Colour; MAP_SIZE]; MAP_SIZE]>, normal_cloud: &Vec<[[Vec3; MAP_SIZE]; MAP_SIZE]>,
+    emissive_maps: &Vec<Lightmap>,
 ) -> (Lightmap, Lightmap) {
     let mut obj_lightmap = object.get_lightmap().unwrap().clone();
     let mut new_emissive_map = Lightmap::default();
@@ -182,7 +182,7 @@ pub fn compute_object_radiosity(
 
             // compute the radiosity of this patch
             let incident: Colour =
-                compute_patch_radiosity(objects, &point_cloud, &normal_cloud, &emissive_maps, sample);
+                compute_patch_radiosity(objects, bvh, march, &point_cloud, &normal_cloud, &emissive_maps, sample);
 
             // divide by pi "because calculus"
             let incident = incident / PI;
@@ -1,116 +1,214 @@
 use crate::{
-    engine::{MAX_MARCH_DISTANCE, MAX_SHAD_IT, SMALL_DISTANCE},
+    bvh::Bvh,
+    engine::{MAX_MARCH_DISTANCE, MAX_SHAD_IT, MIN_LIGHT_SPREAD, SMALL_DISTANCE},
     objects::EngineObject,
     vector::Vec3,
 };
 
 type ObjectRef = Box<dyn EngineObject>;
 
+/// How rays march through the scene
+#[derive(Clone, Copy, Debug)]
+pub struct MarchSettings {
+    /// most steps a ray takes before giving up
+    pub max_steps:       u32,
+    pub max_distance:    f32,
+    /// over-relaxation factor, 1 is plain sphere tracing. Up to 2 takes longer steps, falling back to plain sphere
+    /// tracing if a step overshoots
+    pub relaxation:      f32,
+    /// distance from a surface that counts as a hit, at the start of the ray
+    pub hit_epsilon:     f32,
+    /// how much the hit distance grows per unit travelled
+    pub epsilon_slope:   f32,
+    /// grow the hit distance with the size of a pixel when rendering, on top of `epsilon_slope`
+    pub pixel_footprint: bool,
+    /// bisection steps used to move a hit closer to the surface
+    pub refine_steps:    u32,
+}
+
+impl Default for MarchSettings {
+    fn default() -> Self {
+        Self {
+            max_steps:       256,
+            max_distance:    MAX_MARCH_DISTANCE,
+            relaxation:      1.2,
+            hit_epsilon:     SMALL_DISTANCE,
+            epsilon_slope:   0.0,
+            pixel_footprint: true,
+            refine_steps:    2,
+        }
+    }
+}
+
+/// What a marched ray ran into
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MarchResult {
+    /// hit the object with this index
+    Hit(usize),
+    /// went further than the maximum distance
+    Miss,
+    /// used up every step without hitting or missing, with the closest object at the last step. Usually a ray
+    /// grazing that object
+    OutOfSteps(usize),
+}
+
+impl MarchResult {
+    /// The object that was hit, if any
+    pub fn hit(self) -> Option<usize> {
+        match self {
+            MarchResult::Hit(index) => Some(index),
+            _ => None,
+        }
+    }
+}
+
 pub struct Ray {
     pub position:  Vec3,
     pub direction: Vec3,
 }
 
 impl Ray {
-    pub fn march(&mut self, objects: &Vec<ObjectRef>, ignore_object: Option<usize>) -> Option<usize> {
-        let mut distance_travelled = 0.0;
+    pub fn march(
+        &mut self, objects: &[ObjectRef], bvh: &Bvh, settings: &MarchSettings, ignore_object: Option<usize>,
+    ) -> MarchResult {
         let ignore_index = ignore_object.unwrap_or(usize::MAX);
+        self.trace(objects, bvh, settings, |i| i != ignore_index)
+    }
 
-        while distance_travelled < MAX_MARCH_DISTANCE {
-            let mut distance = f32::INFINITY;
-            let mut closest_object: usize = usize::MAX;
-
-            for (i, object) in objects.iter().enumerate() {
-                if ignore_index == i {
-                    continue;
-                }
+    /// Over-relaxed sphere tracing, as in "Enhanced Sphere Tracing" (Keinert et al. 2014). Steps are `relaxation`
+    /// times the distance to the nearest surface. If the unbounding spheres of two steps stop overlapping, a step
+    /// may have jumped over a surface, so it goes back and carries on with plain sphere tracing
+    fn trace<F: Fn(usize) -> bool>(
+        &mut self, objects: &[ObjectRef], bvh: &Bvh, settings: &MarchSettings, include: F,
+    ) -> MarchResult {
+        let origin = self.position;
+        let mut relaxation = settings.relaxation.clamp(1.0, 2.0);
+        let mut travelled = 0.0;
+        let mut previous = (0.0, 0.0); // distance travelled and to the nearest surface at the last step
+        let mut closest_object = usize::MAX;
+
+        for _ in 0..settings.max_steps {
+            self.position = origin + self.direction * travelled;
+            let (distance, object) = bvh.closest(objects, self.position, &include);
+            closest_object = object;
+
+            if relaxation > 1.0 && distance + previous.1 < travelled - previous.0 {
+                relaxation = 1.0;
+                travelled = previous.0 + previous.1;
+                continue;
+            }
 
-                let obj_distance = object.sdf(self.position);
-                if obj_distance < distance {
-                    distance = obj_distance;
-                    closest_object = i;
-                };
+            let epsilon = settings.hit_epsilon + settings.epsilon_slope * travelled;
+            if distance < epsilon {
+                self.refine(
+                    objects,
+                    bvh,
+                    settings,
+                    &include,
+                    origin,
+                    previous.0,
+                    (travelled, distance),
+                    epsilon,
+                );
+                return MarchResult::Hit(object);
             }
-            if distance < SMALL_DISTANCE {
-                return Some(closest_object);
+            if travelled > settings.max_distance {
+                return MarchResult::Miss;
             }
 
-            distance_travelled += distance;
-            self.position += self.direction * distance;
+            previous = (travelled, distance);
+            travelled += distance * relaxation;
+        }
+
+        if closest_object == usize::MAX {
+            MarchResult::Miss
+        } else {
+            MarchResult::OutOfSteps(closest_object)
         }
-        return None;
     }
 
-    pub fn _internal_march(&mut self, object: &ObjectRef) {
-        let mut iterations: u16 = 0;
-        while iterations < 20 {
-            let distance = object.sdf(self.position);
+    /// Bisect between a point outside a surface and one inside it, leaving the ray at the last point outside.
+    /// `hit` is the distance travelled and distance to the surface where the march stopped, `last` the distance
+    /// travelled at the step before it
+    #[allow(clippy::too_many_arguments)]
+    fn refine<F: Fn(usize) -> bool>(
+        &mut self, objects: &[ObjectRef], bvh: &Bvh, settings: &MarchSettings, include: F, origin: Vec3, last: f32,
+        hit: (f32, f32), epsilon: f32,
+    ) {
+        if settings.refine_steps == 0 {
+            return;
+        }
+        let sdf = |travelled: f32| bvh.closest(objects, origin + self.direction * travelled, &include).0;
+
+        // stopped inside the surface, or just in front of it
+        let (mut outside, mut inside) = if hit.1 < 0.0 {
+            (last, hit.0)
+        } else {
+            (hit.0, hit.0 + 2.0 * epsilon)
+        };
+        if sdf(inside) > 0.0 {
+            return;
+        }
 
-            // looking to exit the shape, so exit if we are outside
-            if distance > SMALL_DISTANCE {
-                break;
+        for _ in 0..settings.refine_steps {
+            let mid = 0.5 * (outside + inside);
+            if sdf(mid) > 0.0 {
+                outside = mid;
+            } else {
+                inside = mid;
             }
-            self.position += self.direction * distance.abs();
-            iterations += 1;
         }
+        self.position = origin + self.direction * outside;
     }
 
-    pub fn radiosity_march(&mut self, objects: &Vec<ObjectRef>, ignore_object: Option<usize>) -> Option<usize> {
+    /// March through the inside of `object` until reaching its surface, returning the distance travelled. The ray
+    /// is left just inside the surface
+    pub fn internal_march(&mut self, object: &ObjectRef, settings: &MarchSettings) -> f32 {
         let mut distance_travelled = 0.0;
-        let ignore_index = ignore_object.unwrap_or(usize::MAX);
 
-        while distance_travelled < MAX_MARCH_DISTANCE {
-            let mut distance = f32::INFINITY;
-            let mut closest_object: usize = usize::MAX;
-
-            for (i, object) in objects.iter().enumerate() {
-                if ignore_index == i || !object.radiosity_collide() {
-                    continue;
-                }
-
-                let obj_distance = object.sdf(self.position);
-                if obj_distance < distance {
-                    distance = obj_distance;
-                    closest_object = i;
-                };
-            }
-            if distance < SMALL_DISTANCE {
-                return Some(closest_object);
+        for _ in 0..settings.max_steps {
+            // looking to exit the shape, so the distance to the surface is the negative of the SDF
+            let distance = -object.sdf(self.position);
+            if distance < settings.hit_epsilon {
+                break;
             }
-
             distance_travelled += distance;
             self.position += self.direction * distance;
+            if distance_travelled > settings.max_distance {
+                break;
+            }
         }
-        return None;
+        distance_travelled
     }
 
+    pub fn radiosity_march(
+        &mut self, objects: &[ObjectRef], bvh: &Bvh, settings: &MarchSettings, ignore_object: Option<usize>,
+    ) -> MarchResult {
+        let ignore_index = ignore_object.unwrap_or(usize::MAX);
+        self.trace(objects, bvh, settings, |i| {
+            i != ignore_index && objects[i].radiosity_collide()
+        })
+    }
+
+    /// March towards a light, returning how much of it is visible. `light_spread` is the tangent of the light's
+    /// angular radius, and sets the width of the penumbra.
     pub fn smooth_shadow_march(
-        &mut self, objects: &Vec<ObjectRef>, ignore_obj_index: usize, light_dist: f32, shading_k: f32,
+        &mut self, objects: &[ObjectRef], bvh: &Bvh, ignore_obj_index: usize, light_dist: f32, light_spread: f32,
     ) -> f32 {
         let mut distance_travelled = 0.0;
         let mut shade: f32 = 1.0; // actually the amount of "not shade"
+        let shading_k = light_spread.max(MIN_LIGHT_SPREAD).recip();
 
         let smoothstep = |x: f32| 3.0 * x.powi(2) - 2.0 * x.powi(3);
 
         for _ in 0..MAX_SHAD_IT {
-            let mut distance = f32::INFINITY;
-
-            for (i, object) in objects.iter().enumerate() {
-                if ignore_obj_index == i {
-                    continue;
-                }
-
-                let obj_distance = object.sdf(self.position);
-                if obj_distance < distance {
-                    distance = obj_distance;
-                };
-            }
+            let (distance, _) = bvh.closest(objects, self.position, |i| i != ignore_obj_index);
 
             shade = shade.min(smoothstep((shading_k * distance / distance_travelled).clamp(0.0, 1.0)));
             //distance = distance.clamp(SMALL_DISTANCE, light_dist / MAX_SHAD_IT as f32);
             distance_travelled += distance; // could clamp this for better res
             self.position += self.direction * distance;
-            if distance < SMALL_DISTANCE || distance_travelled > light_dist {
+            if distance < SMALL_DISTANCE || distance_travelled > light_dist.min(MAX_MARCH_DISTANCE) {
                 break;
             }
         }
@@ -0,0 +1,303 @@
+//! Supersampling: where to put the samples inside each pixel, and how to filter them back into pixels.
+
+use std::str::FromStr;
+
+/// Small xorshift generator, good enough for jittering samples
+#[derive(Clone, Copy, Debug)]
+pub struct Rng(u32);
+
+impl Rng {
+    pub fn new(seed: u32) -> Self {
+        // xorshift gets stuck at 0
+        Rng(seed.wrapping_mul(0x9E37_79B9) | 1)
+    }
+
+    /// Uniform random number in [0, 1)
+    pub fn next_f32(&mut self) -> f32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        (self.0 >> 8) as f32 / (1 << 24) as f32
+    }
+
+    /// Uniform random integer in [0, n)
+    pub fn next_below(&mut self, n: usize) -> usize { ((self.next_f32() * n as f32) as usize).min(n - 1) }
+}
+
+/// How samples are placed inside a pixel
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SamplePattern {
+    /// regular grid
+    Grid,
+    /// regular grid turned so no two samples share a row or column, better on near horizontal and vertical edges
+    RotatedGrid,
+    /// one random sample in each cell of a grid
+    Stratified,
+    /// random samples with exactly one in every row and every column
+    NRooks,
+}
+
+impl FromStr for SamplePattern {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "grid" => Ok(SamplePattern::Grid),
+            "rotated_grid" => Ok(SamplePattern::RotatedGrid),
+            "stratified" => Ok(SamplePattern::Stratified),
+            "n_rooks" => Ok(SamplePattern::NRooks),
+            _ => Err(format!(
+                "unknown sample pattern `{}`, expected grid, rotated_grid, stratified or n_rooks",
+                s
+            )),
+        }
+    }
+}
+
+/// Filter used to turn samples back into pixels. Filters wider than a pixel also use the samples of neighbouring
+/// pixels
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReconstructionFilter {
+    /// plain average of the pixel's own samples
+    Box,
+    /// linear falloff, one pixel wide either side
+    Tent,
+    /// Mitchell-Netravali with B = C = 1/3, sharper than the tent with a little ringing
+    MitchellNetravali,
+}
+
+impl ReconstructionFilter {
+    /// Distance from the pixel centre, in pixels, past which samples have no weight
+    pub fn radius(self) -> f32 {
+        match self {
+            ReconstructionFilter::Box => 0.5,
+            ReconstructionFilter::Tent => 1.0,
+            ReconstructionFilter::MitchellNetravali => 2.0,
+        }
+    }
+
+    /// Weight of a sample `dx`, `dy` pixels from the pixel centre
+    pub fn weight(self, dx: f32, dy: f32) -> f32 { self.weight_1d(dx) * self.weight_1d(dy) }
+
+    fn weight_1d(self, x: f32) -> f32 {
+        let x = x.abs();
+        match self {
+            ReconstructionFilter::Box => (x <= 0.5) as u32 as f32,
+            ReconstructionFilter::Tent => (1.0 - x).max(0.0),
+            ReconstructionFilter::MitchellNetravali => {
+                const B: f32 = 1.0 / 3.0;
+                const C: f32 = 1.0 / 3.0;
+                let weight = if x < 1.0 {
+                    (12.0 - 9.0 * B - 6.0 * C) * x.powi(3) + (-18.0 + 12.0 * B + 6.0 * C) * x.powi(2) + (6.0 - 2.0 * B)
+                } else if x < 2.0 {
+                    (-B - 6.0 * C) * x.powi(3)
+                        + (6.0 * B + 30.0 * C) * x.powi(2)
+                        + (-12.0 * B - 48.0 * C) * x
+                        + (8.0 * B + 24.0 * C)
+                } else {
+                    0.0
+                };
+                weight / 6.0
+            }
+        }
+    }
+}
+
+impl FromStr for ReconstructionFilter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "box" => Ok(ReconstructionFilter::Box),
+            "tent" => Ok(ReconstructionFilter::Tent),
+            "mitchell" => Ok(ReconstructionFilter::MitchellNetravali),
+            _ => Err(format!("unknown filter `{}`, expected box, tent or mitchell", s)),
+        }
+    }
+}
+
+/// Supersampling anti-aliasing settings
+#[derive(Clone, Copy, Debug)]
+pub struct Supersampling {
+    pub pattern: SamplePattern,
+    /// samples per pixel. Grid based patterns round this up to a square number
+    pub samples: u32,
+    pub filter:  ReconstructionFilter,
+}
+
+impl Default for Supersampling {
+    fn default() -> Self {
+        Self {
+            pattern: SamplePattern::RotatedGrid,
+            samples: 1,
+            filter:  ReconstructionFilter::Box,
+        }
+    }
+}
+
+impl Supersampling {
+    /// Whether there's anything to do beyond one sample through the middle of each pixel
+    pub fn enabled(&self) -> bool { self.sample_count() > 1 || self.filter != ReconstructionFilter::Box }
+
+    /// Samples actually taken per pixel
+    pub fn sample_count(&self) -> usize {
+        match self.pattern {
+            SamplePattern::NRooks => self.samples.max(1) as usize,
+            _ => self.grid_size().pow(2),
+        }
+    }
+
+    fn grid_size(&self) -> usize { (self.samples.max(1) as f32).sqrt().ceil() as usize }
+
+    /// Fill `offsets` with sample positions relative to the pixel centre, in pixels. `rng` is only used by the
+    /// random patterns
+    pub fn offsets(&self, rng: &mut Rng, offsets: &mut Vec<(f32, f32)>) {
+        offsets.clear();
+        let k = self.grid_size();
+        let cell = (k as f32).recip();
+
+        match self.pattern {
+            SamplePattern::Grid => {
+                for i in 0..k {
+                    for j in 0..k {
+                        offsets.push(((i as f32 + 0.5) * cell - 0.5, (j as f32 + 0.5) * cell - 0.5));
+                    }
+                }
+            }
+            SamplePattern::RotatedGrid => {
+                // rotating by atan(1/k) puts every sample in its own row and column, then wrap back into the pixel
+                let (sin, cos) = cell.atan().sin_cos();
+                for i in 0..k {
+                    for j in 0..k {
+                        let (x, y) = ((i as f32 + 0.5) * cell - 0.5, (j as f32 + 0.5) * cell - 0.5);
+                        let (x, y) = (x * cos - y * sin, x * sin + y * cos);
+                        offsets.push(((x + 0.5).rem_euclid(1.0) - 0.5, (y + 0.5).rem_euclid(1.0) - 0.5));
+                    }
+                }
+            }
+            SamplePattern::Stratified => {
+                for i in 0..k {
+                    for j in 0..k {
+                        offsets.push((
+                            (i as f32 + rng.next_f32()) * cell - 0.5,
+                            (j as f32 + rng.next_f32()) * cell - 0.5,
+                        ));
+                    }
+                }
+            }
+            SamplePattern::NRooks => {
+                let n = self.sample_count();
+                let cell = (n as f32).recip();
+                for i in 0..n {
+                    offsets.push((
+                        (i as f32 + rng.next_f32()) * cell - 0.5,
+                        (i as f32 + rng.next_f32()) * cell - 0.5,
+                    ));
+                }
+                // shuffle the rows so the samples aren't all on the diagonal
+                for i in (1..n).rev() {
+                    let j = rng.next_below(i + 1);
+                    let (y_i, y_j) = (offsets[i].1, offsets[j].1);
+                    offsets[i].1 = y_j;
+                    offsets[j].1 = y_i;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PATTERNS: [SamplePattern; 4] = [
+        SamplePattern::Grid,
+        SamplePattern::RotatedGrid,
+        SamplePattern::Stratified,
+        SamplePattern::NRooks,
+    ];
+
+    fn offsets(pattern: SamplePattern, samples: u32) -> Vec<(f32, f32)> {
+        let ssaa = Supersampling {
+            pattern,
+            samples,
+            filter: ReconstructionFilter::Box,
+        };
+        let mut offsets = Vec::new();
+        ssaa.offsets(&mut Rng::new(7), &mut offsets);
+        assert_eq!(offsets.len(), ssaa.sample_count());
+        offsets
+    }
+
+    #[test]
+    fn samples_inside_pixel() {
+        for &pattern in &PATTERNS {
+            for samples in 1..=20 {
+                for (x, y) in offsets(pattern, samples) {
+                    assert!((-0.5..0.5).contains(&x) && (-0.5..0.5).contains(&y), "{:?}", pattern);
+                }
+            }
+        }
+        // only n-rooks can take any number of samples
+        assert_eq!(offsets(SamplePattern::Grid, 5).len(), 9);
+        assert_eq!(offsets(SamplePattern::NRooks, 5).len(), 5);
+        assert_eq!(offsets(SamplePattern::Grid, 1), vec![(0.0, 0.0)]);
+    }
+
+    #[test]
+    fn one_sample_per_row_and_column() {
+        let rooks = offsets(SamplePattern::NRooks, 11);
+        for axis in 0..2 {
+            let mut taken = vec![false; rooks.len()];
+            for &(x, y) in &rooks {
+                let slot = (([x, y][axis] + 0.5) * rooks.len() as f32) as usize;
+                assert!(!taken[slot], "two samples in one row or column");
+                taken[slot] = true;
+            }
+        }
+
+        // the rotated grid lines up with nothing
+        for samples in 2..=16 {
+            let offsets = offsets(SamplePattern::RotatedGrid, samples);
+            for (i, a) in offsets.iter().enumerate() {
+                for b in &offsets[..i] {
+                    assert!(
+                        (a.0 - b.0).abs() > 1e-3 && (a.1 - b.1).abs() > 1e-3,
+                        "{} samples",
+                        samples
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn filter_weights() {
+        let filters = [
+            ReconstructionFilter::Box,
+            ReconstructionFilter::Tent,
+            ReconstructionFilter::MitchellNetravali,
+        ];
+        for &filter in &filters {
+            let radius = filter.radius();
+            assert_eq!(filter.weight(radius + 0.01, 0.0), 0.0);
+            assert_eq!(filter.weight(0.0, -radius - 0.01), 0.0);
+
+            // each filter integrates to one, so it doesn't brighten or darken the image
+            let steps = 1000;
+            let dx = 2.0 * radius / steps as f32;
+            let area: f32 = (0..steps)
+                .map(|i| filter.weight_1d(-radius + (i as f32 + 0.5) * dx) * dx)
+                .sum();
+            assert!((area - 1.0).abs() < 1e-3, "{:?} integrates to {}", filter, area);
+        }
+        assert_eq!(ReconstructionFilter::Tent.weight(0.5, 0.5), 0.25);
+    }
+
+    #[test]
+    fn names() {
+        assert_eq!("n_rooks".parse(), Ok(SamplePattern::NRooks));
+        assert_eq!("mitchell".parse(), Ok(ReconstructionFilter::MitchellNetravali));
+        assert!("poisson".parse::<SamplePattern>().unwrap_err().contains("`poisson`"));
+    }
+}
@@ -0,0 +1,1802 @@
+//! Scene description files.
+//!
+//! A scene file is a list of statements, one per line (or separated by `;`). Each statement is a name
+//! followed by arguments, and optionally a `{ ... }` block of nested statements. `#` starts a comment.
+//!
+//! ```text
+//! sky 135 206 235
+//! camera { position 0 0.5 -3.5; look_at 0 0 0; fov 90 }
+//! sphere_light { position 2 -1 1.5; radius 0.25; intensity 3.5 }
+//! texture wood "../textures/Floor128.bmp" 32 32 srgb
+//! material mirror { reflectivity 1; diffuse 0.03 }
+//! sphere { position -1.2 -1 0.1; radius 1; material mirror; colour white }
+//! yplane { y -2; dir 1; texture wood }
+//! ```
+//!
+//! Besides spheres and planes there are `box`, `rounded_box`, `torus`, `cylinder`, `capsule`, `cone`, `ellipsoid`,
+//! `hex_prism` and `octahedron`, all centred on their `position`:
+//!
+//! ```text
+//! box { position 0 -1.5 0; size 1 1 2 }
+//! rounded_box { size 1 1 1; radius 0.1 }
+//! torus { major_radius 1; minor_radius 0.25 }
+//! cylinder { radius 0.5; height 2 }       # also hex_prism, where radius is to the middle of a side
+//! capsule { radius 0.5; length 1 }        # length between the centres of the ends
+//! cone { radius 1; top_radius 0; height 2 }
+//! ellipsoid { radii 1 0.5 0.75 }
+//! octahedron { radius 1 }
+//! ```
+//!
+//! Objects can be combined with `union`, `intersection` and `subtraction` blocks, which nest. A `blend` radius melts
+//! them together, with a polynomial smooth minimum unless it says `exponential`:
+//!
+//! ```text
+//! subtraction {
+//!     blend 0.1
+//!     union { blend 0.3 exponential; sphere { radius 1 }; box { position 0 -1 0; size 1.5 1 1.5 } }
+//!     cylinder { radius 0.4; height 4 }
+//! }
+//! ```
+//!
+//! Any object can be moved, turned and stretched by a `transform` around it, applied in order:
+//!
+//! ```text
+//! transform {
+//!     scale 1 0.5 1
+//!     rotate 45 0 1 0     # degrees about the axis
+//!     translate 0 -1 0
+//!     box { size 1 1 1 }
+//! }
+//! ```
+//!
+//! Domain modifiers wrap one object built around the origin: `repeat { spacing X Y Z; limit X Y Z }` (without a
+//! limit it goes on forever, a spacing of 0 doesn't repeat along that axis), `mirror { axes x z }`,
+//! `twist { rate DEGREES }` per unit up y, `bend { rate DEGREES }` per unit along x, `elongate { length X Y Z }`,
+//! `round { radius R }` and `onion { thickness T }`:
+//!
+//! ```text
+//! transform {
+//!     translate 0 -1.5 0
+//!     repeat { spacing 1 0 1; limit 2 0 2; twist { rate 90; box { size 0.3 1 0.3 } } }
+//! }
+//! ```
+//!
+//! Fractals are `mandelbulb`, `menger_sponge`, `sierpinski` and `mandelbox`, each taking an `iterations` count, a
+//! `bailout` and a `power`. The bailout is the escape radius, except for the sponge where it's how many cells from the
+//! surface it stops cutting holes. The power is the exponent of the Mandelbulb and the scale of each level for the
+//! others. They're coloured by an orbit trap, from `trap_colour` where orbits come close to the centre to `colour`:
+//!
+//! ```text
+//! mandelbulb { position 0 -1 0; size 0.8; iterations 8; power 8; colour white; trap_colour soft_red }
+//! menger_sponge { size 0.5; iterations 4 }        # fills the cube from -size to size
+//! mandelbox { size 0.1; power -1.5; bailout 16 }
+//! ```
+//!
+//! Ray marching can be tuned with a `march { max_steps 256; relaxation 1.2; epsilon 0.001; refine_steps 2 }` block,
+//! reflections and refractions with `bounces { max_depth 8; min_throughput 0.01; russian_roulette off }`, and
+//! anti-aliasing with `supersampling { samples 4; pattern rotated_grid; filter mitchell }`.
+//!
+//! A `post { ... }` block replaces the post-processing passes, which run in the order they're listed:
+//!
+//! ```text
+//! post {
+//!     auto_exposure { key 0.18; speed 1.5; enabled off }
+//!     exposure 1.5
+//!     bloom { threshold 1; intensity 0.3; radius 8 }
+//!     tonemap reinhard 4
+//!     grade { saturation 1.1; contrast 1.05; tint 255 245 235 }
+//!     vignette { strength 0.4; radius 0.5; enabled off }
+//!     output srgb
+//!     fxaa { span_max 8 }
+//! }
+//! ```
+//!
+//! `tonemap` takes `aces`, `hable`, `agx`, `clamp` or `reinhard` with an optional white point.
+//!
+//! Colours are either three 8-bit sRGB values or one of the named colours in `colour.rs`. Lights can also take a
+//! `temperature` in Kelvin instead of a colour.
+//! Paths are relative to the scene file.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::camera::Camera;
+use crate::colour::{kelvin_to_rgb, Colour, SOFT_GRAY, SOFT_GREEN, SOFT_RED, SOFT_YELLOW, WHITE};
+use crate::csg::{Blend, Csg, Operation};
+use crate::engine::{BounceSettings, Engine, LightRef, ObjectRef, SKY_COLOUR};
+use crate::fractals::{Mandelbox, Mandelbulb, MengerSponge, SierpinskiTetrahedron};
+use crate::fxaa::Fxaa;
+use crate::lights::{DirectionalLight, PointLight, SphereLight, SpotLight};
+use crate::material::Material;
+use crate::modifiers::{Modified, Modifier};
+use crate::objects::{
+    Capsule, Cone, Cuboid, Cylinder, Ellipsoid, HexPrism, Octahedron, RoundedBox, Sphere, Torus, XPlane, YPlane, ZPlane,
+};
+use crate::postprocess::{
+    AutoExposure, Bloom, ColourGrade, Exposure, OutputTransfer, PostProcess, Sharpen, Tonemap, Tonemapper, Transfer,
+    Vignette,
+};
+use crate::ray::MarchSettings;
+use crate::sampling::Supersampling;
+use crate::texture::{ColourSpace, Texture};
+use crate::transform::Transformed;
+use crate::vector::{Mat4, Quat, Vec3};
+
+pub const DEFAULT_SCENE: &str = "assets/scenes/default.scene";
+
+/// Statements that make an object
+const OBJECTS: &[&str] = &[
+    "sphere",
+    "xplane",
+    "yplane",
+    "zplane",
+    "box",
+    "rounded_box",
+    "torus",
+    "cylinder",
+    "capsule",
+    "cone",
+    "ellipsoid",
+    "hex_prism",
+    "octahedron",
+    "union",
+    "intersection",
+    "subtraction",
+    "transform",
+    "repeat",
+    "mirror",
+    "twist",
+    "bend",
+    "elongate",
+    "round",
+    "onion",
+    "mandelbulb",
+    "menger_sponge",
+    "sierpinski",
+    "mandelbox",
+];
+
+const NAMED_COLOURS: &[(&str, Colour)] = &[
+    ("white", WHITE),
+    ("soft_red", SOFT_RED),
+    ("soft_green", SOFT_GREEN),
+    ("soft_gray", SOFT_GRAY),
+    ("soft_yellow", SOFT_YELLOW),
+];
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io(io::Error),
+    Parse {
+        line:    usize,
+        column:  usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SceneError::Io(e) => write!(f, "{}", e),
+            SceneError::Parse { line, column, message } => write!(f, "{}:{}: {}", line, column, message),
+        }
+    }
+}
+
+impl std::error::Error for SceneError {}
+
+type SceneResult<T> = Result<T, SceneError>;
+
+/// Load a scene file and build an engine from it. Lightmaps still need computing.
+pub fn load_scene<P: AsRef<Path>>(path: P) -> SceneResult<Engine> {
+    let path = path.as_ref();
+    let source = fs::read_to_string(path).map_err(SceneError::Io)?;
+    parse_scene(&source, path.parent().unwrap_or_else(|| Path::new("")))
+}
+
+/// Build an engine from scene source, resolving texture paths against `base_dir`
+pub fn parse_scene(source: &str, base_dir: &Path) -> SceneResult<Engine> {
+    let tokens = tokenize(source)?;
+    let mut parser = Parser {
+        tokens: &tokens,
+        index:  0,
+    };
+    let statements = parser.parse_block(None)?;
+
+    let mut builder = SceneBuilder {
+        base_dir:   base_dir.to_path_buf(),
+        textures:   HashMap::new(),
+        materials:  HashMap::new(),
+        objects:    Vec::new(),
+        camera:     Camera::default(),
+        lights:     Vec::new(),
+        sky_colour: SKY_COLOUR,
+        march:      MarchSettings::default(),
+        bounces:    BounceSettings::default(),
+        ssaa:       Supersampling::default(),
+        post:       PostProcess::standard(),
+    };
+    for statement in &statements {
+        builder.statement(statement)?;
+    }
+
+    let mut engine = Engine::new(builder.objects, builder.camera, builder.lights, builder.sky_colour);
+    engine.march = builder.march;
+    engine.bounces = builder.bounces;
+    engine.ssaa = builder.ssaa;
+    engine.post = builder.post;
+    Ok(engine)
+}
+
+fn error(line: usize, column: usize, message: impl Into<String>) -> SceneError {
+    SceneError::Parse {
+        line,
+        column,
+        message: message.into(),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum TokenKind {
+    Ident(String),
+    Number(f32),
+    Str(String),
+    Open,
+    Close,
+    EndOfLine,
+    EndOfFile,
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    kind:   TokenKind,
+    line:   usize,
+    column: usize,
+}
+
+impl Token {
+    fn describe(&self) -> String {
+        match &self.kind {
+            TokenKind::Ident(name) => format!("`{}`", name),
+            TokenKind::Number(n) => format!("`{}`", n),
+            TokenKind::Str(s) => format!("\"{}\"", s),
+            TokenKind::Open => "`{`".to_string(),
+            TokenKind::Close => "`}`".to_string(),
+            TokenKind::EndOfLine => "end of line".to_string(),
+            TokenKind::EndOfFile => "end of file".to_string(),
+        }
+    }
+}
+
+fn tokenize(source: &str) -> SceneResult<Vec<Token>> {
+    let mut tokens = Vec::new();
+
+    for (line_index, line) in source.lines().enumerate() {
+        let line_number = line_index + 1;
+        let chars: Vec<char> = line.chars().collect();
+        let mut i = 0;
+
+        while i < chars.len() {
+            let c = chars[i];
+            let column = i + 1;
+            let token = |kind| Token {
+                kind,
+                line: line_number,
+                column,
+            };
+
+            if c == '#' {
+                break;
+            } else if c.is_whitespace() {
+                i += 1;
+            } else if c == '{' || c == '}' || c == ';' {
+                tokens.push(token(match c {
+                    '{' => TokenKind::Open,
+                    '}' => TokenKind::Close,
+                    _ => TokenKind::EndOfLine,
+                }));
+                i += 1;
+            } else if c == '"' {
+                let start = i + 1;
+                let end = match chars[start..].iter().position(|&c| c == '"') {
+                    Some(length) => start + length,
+                    None => return Err(error(line_number, column, "unterminated string")),
+                };
+                tokens.push(token(TokenKind::Str(chars[start..end].iter().collect())));
+                i = end + 1;
+            } else if c.is_ascii_digit() || c == '-' || c == '+' || c == '.' {
+                let start = i;
+                while i < chars.len() && (chars[i].is_ascii_alphanumeric() || "+-.".contains(chars[i])) {
+                    i += 1;
+                }
+                let text: String = chars[start..i].iter().collect();
+                match text.parse() {
+                    Ok(n) => tokens.push(token(TokenKind::Number(n))),
+                    Err(_) => return Err(error(line_number, column, format!("invalid number `{}`", text))),
+                }
+            } else if c.is_alphabetic() || c == '_' {
+                let start = i;
+                while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                    i += 1;
+                }
+                tokens.push(token(TokenKind::Ident(chars[start..i].iter().collect())));
+            } else {
+                return Err(error(line_number, column, format!("unexpected character `{}`", c)));
+            }
+        }
+
+        tokens.push(Token {
+            kind:   TokenKind::EndOfLine,
+            line:   line_number,
+            column: chars.len() + 1,
+        });
+    }
+
+    let last_line = source.lines().count().max(1);
+    tokens.push(Token {
+        kind:   TokenKind::EndOfFile,
+        line:   last_line,
+        column: source.lines().last().map_or(0, |l| l.chars().count()) + 1,
+    });
+    Ok(tokens)
+}
+
+/// A name, its arguments, and an optional block of nested statements
+#[derive(Debug)]
+struct Statement {
+    name:   String,
+    args:   Vec<Token>,
+    block:  Option<Vec<Statement>>,
+    line:   usize,
+    column: usize,
+}
+
+struct Parser<'a> {
+    tokens: &'a [Token],
+    index:  usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> &'a Token { &self.tokens[self.index] }
+
+    fn next(&mut self) -> &'a Token {
+        let token = &self.tokens[self.index];
+        if token.kind != TokenKind::EndOfFile {
+            self.index += 1;
+        }
+        token
+    }
+
+    /// Parse statements until the closing brace of `open`, or the end of the file if there is no `open`
+    fn parse_block(&mut self, open: Option<&Token>) -> SceneResult<Vec<Statement>> {
+        let mut statements = Vec::new();
+
+        loop {
+            let token = self.next();
+            match &token.kind {
+                TokenKind::EndOfLine => continue,
+                TokenKind::Close if open.is_some() => return Ok(statements),
+                TokenKind::EndOfFile if open.is_none() => return Ok(statements),
+                TokenKind::EndOfFile => {
+                    let open = open.unwrap();
+                    return Err(error(open.line, open.column, "`{` is never closed"));
+                }
+                TokenKind::Ident(name) => statements.push(self.parse_statement(name, token)?),
+                _ => {
+                    return Err(error(
+                        token.line,
+                        token.column,
+                        format!("unexpected {}", token.describe()),
+                    ))
+                }
+            }
+        }
+    }
+
+    fn parse_statement(&mut self, name: &str, name_token: &Token) -> SceneResult<Statement> {
+        let mut statement = Statement {
+            name:   name.to_string(),
+            args:   Vec::new(),
+            block:  None,
+            line:   name_token.line,
+            column: name_token.column,
+        };
+
+        loop {
+            let token = self.peek();
+            match token.kind {
+                TokenKind::Ident(_) | TokenKind::Number(_) | TokenKind::Str(_) => {
+                    statement.args.push(self.next().clone());
+                }
+                TokenKind::Open => {
+                    self.next();
+                    statement.block = Some(self.parse_block(Some(token))?);
+                    break;
+                }
+                _ => break,
+            }
+        }
+
+        // a statement must end the line, or close the block it is in
+        let token = self.peek();
+        match token.kind {
+            TokenKind::EndOfLine | TokenKind::Close | TokenKind::EndOfFile => Ok(statement),
+            _ => Err(error(
+                token.line,
+                token.column,
+                format!("expected end of line, found {}", token.describe()),
+            )),
+        }
+    }
+}
+
+impl Statement {
+    fn error(&self, message: impl Into<String>) -> SceneError { error(self.line, self.column, message) }
+
+    fn unknown(&self, context: &str) -> SceneError { self.error(format!("unknown {} `{}`", context, self.name)) }
+
+    fn block(&self) -> SceneResult<&[Statement]> {
+        match &self.block {
+            Some(block) => Ok(block),
+            None => Err(self.error(format!("`{}` needs a `{{ ... }}` block", self.name))),
+        }
+    }
+
+    fn expect_args(&self, count: usize) -> SceneResult<()> {
+        if self.block.is_some() {
+            return Err(self.error(format!("`{}` does not take a block", self.name)));
+        }
+        if self.args.len() != count {
+            return Err(self.error(format!(
+                "`{}` takes {} value{}, found {}",
+                self.name,
+                count,
+                if count == 1 { "" } else { "s" },
+                self.args.len()
+            )));
+        }
+        Ok(())
+    }
+
+    fn arg_number(&self, index: usize) -> SceneResult<f32> {
+        let arg = &self.args[index];
+        match arg.kind {
+            TokenKind::Number(n) => Ok(n),
+            _ => Err(error(
+                arg.line,
+                arg.column,
+                format!("expected a number, found {}", arg.describe()),
+            )),
+        }
+    }
+
+    fn arg_ident(&self, index: usize) -> SceneResult<&str> {
+        let arg = &self.args[index];
+        match &arg.kind {
+            TokenKind::Ident(name) => Ok(name),
+            _ => Err(error(
+                arg.line,
+                arg.column,
+                format!("expected a name, found {}", arg.describe()),
+            )),
+        }
+    }
+
+    fn arg_string(&self, index: usize) -> SceneResult<&str> {
+        let arg = &self.args[index];
+        match &arg.kind {
+            TokenKind::Str(s) => Ok(s),
+            _ => Err(error(
+                arg.line,
+                arg.column,
+                format!("expected a quoted string, found {}", arg.describe()),
+            )),
+        }
+    }
+
+    fn number(&self) -> SceneResult<f32> {
+        self.expect_args(1)?;
+        self.arg_number(0)
+    }
+
+    fn positive_number(&self) -> SceneResult<f32> {
+        let n = self.number()?;
+        if n <= 0.0 {
+            return Err(self.error(format!("`{}` must be greater than zero", self.name)));
+        }
+        Ok(n)
+    }
+
+    fn non_negative_number(&self) -> SceneResult<f32> {
+        let n = self.number()?;
+        if n < 0.0 {
+            return Err(self.error(format!("`{}` can't be negative", self.name)));
+        }
+        Ok(n)
+    }
+
+    /// A number from 0 to 1
+    fn fraction(&self) -> SceneResult<f32> {
+        let n = self.number()?;
+        if !(0.0..=1.0).contains(&n) {
+            return Err(self.error(format!("`{}` must be between 0 and 1", self.name)));
+        }
+        Ok(n)
+    }
+
+    /// A whole number, zero or more
+    fn whole_number(&self) -> SceneResult<u32> {
+        let n = self.number()?;
+        if n < 0.0 || n.fract() != 0.0 || n > u32::MAX as f32 {
+            return Err(self.error(format!("`{}` must be a whole number, zero or more", self.name)));
+        }
+        Ok(n as u32)
+    }
+
+    /// A whole number of at least one
+    fn count(&self) -> SceneResult<u32> {
+        let n = self.whole_number()?;
+        if n == 0 {
+            return Err(self.error(format!("`{}` must be at least 1", self.name)));
+        }
+        Ok(n)
+    }
+
+    /// A single name parsed into `T`
+    fn parse<T: FromStr<Err = String>>(&self) -> SceneResult<T> {
+        self.expect_args(1)?;
+        self.arg_ident(0)?.parse().map_err(|e: String| self.error(e))
+    }
+
+    /// `on` or `off`
+    fn switch(&self) -> SceneResult<bool> {
+        self.expect_args(1)?;
+        match self.arg_ident(0)? {
+            "on" => Ok(true),
+            "off" => Ok(false),
+            _ => Err(self.error(format!("`{}` must be `on` or `off`", self.name))),
+        }
+    }
+
+    fn vec3(&self) -> SceneResult<Vec3> {
+        self.expect_args(3)?;
+        Ok(Vec3::new(self.arg_number(0)?, self.arg_number(1)?, self.arg_number(2)?))
+    }
+
+    /// A vector that gets normalised, so it can't be zero
+    fn direction(&self) -> SceneResult<Vec3> {
+        let v = self.vec3()?;
+        if v.mag_sqd() == 0.0 {
+            return Err(self.error(format!("`{}` can't be a zero vector", self.name)));
+        }
+        Ok(v.normalized())
+    }
+
+    fn colour(&self) -> SceneResult<Colour> {
+        if self.args.len() == 1 {
+            let name = self.arg_ident(0)?;
+            return match NAMED_COLOURS.iter().find(|(n, _)| *n == name) {
+                Some((_, colour)) => Ok(*colour),
+                None => Err(self.error(format!("unknown colour `{}`", name))),
+            };
+        }
+
+        self.expect_args(3)?;
+        let mut channels = [0.0; 3];
+        for (i, channel) in channels.iter_mut().enumerate() {
+            *channel = self.arg_number(i)?;
+            if !(0.0..=255.0).contains(channel) {
+                let arg = &self.args[i];
+                return Err(error(arg.line, arg.column, "colour channels must be between 0 and 255"));
+            }
+        }
+        Ok(rgb![channels[0], channels[1], channels[2]])
+    }
+}
+
+struct SceneBuilder {
+    base_dir:   PathBuf,
+    textures:   HashMap<String, Texture>,
+    materials:  HashMap<String, Material>,
+    objects:    Vec<ObjectRef>,
+    camera:     Camera,
+    lights:     Vec<LightRef>,
+    sky_colour: Colour,
+    march:      MarchSettings,
+    bounces:    BounceSettings,
+    ssaa:       Supersampling,
+    post:       PostProcess,
+}
+
+impl SceneBuilder {
+    fn statement(&mut self, statement: &Statement) -> SceneResult<()> {
+        match statement.name.as_str() {
+            "sky" => self.sky_colour = statement.colour()?,
+            "camera" => self.camera(statement)?,
+            "march" => self.march(statement)?,
+            "bounces" => self.bounces(statement)?,
+            "supersampling" => self.supersampling(statement)?,
+            "post" => self.post(statement)?,
+            "point_light" | "directional_light" | "spot_light" | "sphere_light" => {
+                let light = self.light(statement)?;
+                self.lights.push(light);
+            }
+            "texture" => self.texture(statement)?,
+            "material" => {
+                let name = match statement.args.len() {
+                    1 => statement.arg_ident(0)?.to_string(),
+                    _ => return Err(statement.error("`material` takes a name and a `{ ... }` block")),
+                };
+                let material = self.material_block(statement)?;
+                self.materials.insert(name, material);
+            }
+            name if OBJECTS.contains(&name) => {
+                let object = self.object(statement)?;
+                self.objects.push(object);
+            }
+            _ => return Err(statement.unknown("statement")),
+        }
+        Ok(())
+    }
+
+    /// Angles are in degrees. `look_at` is applied after `position`, and before any explicit angles.
+    fn camera(&mut self, statement: &Statement) -> SceneResult<()> {
+        let mut camera = Camera::default();
+        let mut look_at = None;
+        let mut angles = [None; 3];
+
+        for property in statement.block()? {
+            match property.name.as_str() {
+                "position" => camera.position = property.vec3()?,
+                "look_at" => look_at = Some((property, property.vec3()?)),
+                "yaw" => angles[0] = Some(property.number()?.to_radians()),
+                "pitch" => angles[1] = Some(property.number()?.to_radians()),
+                "roll" => angles[2] = Some(property.number()?.to_radians()),
+                "fov" => {
+                    camera.fov = property.positive_number()?;
+                    if camera.fov >= 180.0 {
+                        return Err(property.error("`fov` must be less than 180 degrees"));
+                    }
+                }
+                _ => return Err(property.unknown("camera property")),
+            }
+        }
+
+        if let Some((property, target)) = look_at {
+            if target - camera.position == Vec3::default() {
+                return Err(property.error("`look_at` can't be the camera's position"));
+            }
+            camera.point_along(target - camera.position);
+        }
+        camera.yaw = angles[0].unwrap_or(camera.yaw);
+        camera.pitch = angles[1].unwrap_or(camera.pitch);
+        camera.roll = angles[2].unwrap_or(camera.roll);
+
+        self.camera = camera;
+        Ok(())
+    }
+
+    /// Ray marching settings, anything not given keeps its default
+    fn march(&mut self, statement: &Statement) -> SceneResult<()> {
+        let march = &mut self.march;
+        for property in statement.block()? {
+            match property.name.as_str() {
+                "max_steps" => march.max_steps = property.count()?,
+                "max_distance" => march.max_distance = property.positive_number()?,
+                "relaxation" => {
+                    march.relaxation = property.number()?;
+                    if !(1.0..=2.0).contains(&march.relaxation) {
+                        return Err(property.error("`relaxation` must be between 1 and 2"));
+                    }
+                }
+                "epsilon" => march.hit_epsilon = property.positive_number()?,
+                "epsilon_slope" => march.epsilon_slope = property.non_negative_number()?,
+                "pixel_footprint" => march.pixel_footprint = property.switch()?,
+                "refine_steps" => march.refine_steps = property.whole_number()?,
+                _ => return Err(property.unknown("march property")),
+            }
+        }
+        Ok(())
+    }
+
+    /// Limits on reflection and refraction rays, anything not given keeps its default
+    fn bounces(&mut self, statement: &Statement) -> SceneResult<()> {
+        let bounces = &mut self.bounces;
+        for property in statement.block()? {
+            match property.name.as_str() {
+                "max_depth" => bounces.max_depth = property.whole_number()?,
+                "min_throughput" => bounces.min_throughput = property.non_negative_number()?,
+                "russian_roulette" => bounces.russian_roulette = property.switch()?,
+                _ => return Err(property.unknown("bounces property")),
+            }
+        }
+        Ok(())
+    }
+
+    /// Anti-aliasing, anything not given keeps its default
+    fn supersampling(&mut self, statement: &Statement) -> SceneResult<()> {
+        let ssaa = &mut self.ssaa;
+        for property in statement.block()? {
+            match property.name.as_str() {
+                "samples" => ssaa.samples = property.count()?,
+                "pattern" => ssaa.pattern = property.parse()?,
+                "filter" => ssaa.filter = property.parse()?,
+                _ => return Err(property.unknown("supersampling property")),
+            }
+        }
+        Ok(())
+    }
+
+    /// The post-processing passes, in order. Passes with a block can be listed but turned off with `enabled off`,
+    /// so they can be toggled on in the viewer
+    fn post(&mut self, statement: &Statement) -> SceneResult<()> {
+        let mut post = PostProcess::default();
+        for pass in statement.block()? {
+            let mut enabled = true;
+            match pass.name.as_str() {
+                "exposure" => post.push(Exposure(pass.positive_number()?), enabled),
+                "tonemap" => post.push(Tonemap(self.tonemapper(pass)?), enabled),
+                "auto_exposure" => {
+                    let mut auto = AutoExposure::default();
+                    for property in pass.block()? {
+                        match property.name.as_str() {
+                            "enabled" => enabled = property.switch()?,
+                            "key" => auto.key = property.positive_number()?,
+                            "low" => auto.low = property.fraction()?,
+                            "high" => auto.high = property.fraction()?,
+                            "speed" => auto.speed = property.positive_number()?,
+                            "min_exposure" => auto.min_exposure = property.positive_number()?,
+                            "max_exposure" => auto.max_exposure = property.positive_number()?,
+                            _ => return Err(property.unknown("auto_exposure property")),
+                        }
+                    }
+                    if auto.low >= auto.high || auto.min_exposure > auto.max_exposure {
+                        return Err(pass.error("auto_exposure needs low < high and min_exposure <= max_exposure"));
+                    }
+                    post.push(auto, enabled);
+                }
+                "output" => post.push(OutputTransfer(self.transfer(pass)?), enabled),
+                "grade" => {
+                    let mut grade = ColourGrade::default();
+                    for property in pass.block()? {
+                        match property.name.as_str() {
+                            "enabled" => enabled = property.switch()?,
+                            "tint" => grade.tint = property.colour()?,
+                            "saturation" => grade.saturation = property.non_negative_number()?,
+                            "contrast" => grade.contrast = property.positive_number()?,
+                            _ => return Err(property.unknown("grade property")),
+                        }
+                    }
+                    post.push(grade, enabled);
+                }
+                "bloom" => {
+                    let mut bloom = Bloom::default();
+                    for property in pass.block()? {
+                        match property.name.as_str() {
+                            "enabled" => enabled = property.switch()?,
+                            "threshold" => bloom.threshold = property.non_negative_number()?,
+                            "intensity" => bloom.intensity = property.non_negative_number()?,
+                            "radius" => bloom.radius = property.positive_number()?,
+                            _ => return Err(property.unknown("bloom property")),
+                        }
+                    }
+                    post.push(bloom, enabled);
+                }
+                "vignette" => {
+                    let mut vignette = Vignette::default();
+                    for property in pass.block()? {
+                        match property.name.as_str() {
+                            "enabled" => enabled = property.switch()?,
+                            "strength" => vignette.strength = property.fraction()?,
+                            "radius" => vignette.radius = property.fraction()?,
+                            _ => return Err(property.unknown("vignette property")),
+                        }
+                    }
+                    post.push(vignette, enabled);
+                }
+                "sharpen" => {
+                    let mut sharpen = Sharpen::default();
+                    for property in pass.block()? {
+                        match property.name.as_str() {
+                            "enabled" => enabled = property.switch()?,
+                            "amount" => sharpen.amount = property.non_negative_number()?,
+                            _ => return Err(property.unknown("sharpen property")),
+                        }
+                    }
+                    post.push(sharpen, enabled);
+                }
+                "fxaa" => {
+                    let mut fxaa = Fxaa::default();
+                    for property in pass.block()? {
+                        match property.name.as_str() {
+                            "enabled" => enabled = property.switch()?,
+                            "span_max" => fxaa.span_max = property.positive_number()?,
+                            "reduce_mul" => fxaa.reduce_mul = property.non_negative_number()?,
+                            "reduce_min" => fxaa.reduce_min = property.positive_number()?,
+                            _ => return Err(property.unknown("fxaa property")),
+                        }
+                    }
+                    post.push(fxaa, enabled);
+                }
+                _ => return Err(pass.unknown("post-process pass")),
+            }
+        }
+        self.post = post;
+        Ok(())
+    }
+
+    /// `tonemap NAME`, or `tonemap reinhard WHITE` for Reinhard with a white point
+    fn tonemapper(&self, statement: &Statement) -> SceneResult<Tonemapper> {
+        if statement.args.len() == 2 && statement.block.is_none() && statement.arg_ident(0)? == "reinhard" {
+            let white = statement.arg_number(1)?;
+            if white <= 0.0 {
+                return Err(statement.error("the white point must be greater than zero"));
+            }
+            return Ok(Tonemapper::ReinhardExtended(white));
+        }
+        statement.parse()
+    }
+
+    /// `output srgb`, `output linear` or `output gamma G`
+    fn transfer(&self, statement: &Statement) -> SceneResult<Transfer> {
+        if statement.block.is_some() || statement.args.is_empty() {
+            return Err(statement.error("`output` takes `srgb`, `linear`, or `gamma` and a value"));
+        }
+        match (statement.arg_ident(0)?, statement.args.len()) {
+            ("srgb", 1) => Ok(Transfer::Srgb),
+            ("linear", 1) => Ok(Transfer::Linear),
+            ("gamma", 2) => {
+                let gamma = statement.arg_number(1)?;
+                if gamma <= 0.0 {
+                    return Err(statement.error("gamma must be greater than zero"));
+                }
+                Ok(Transfer::Gamma(gamma))
+            }
+            _ => Err(statement.error("`output` takes `srgb`, `linear`, or `gamma` and a value")),
+        }
+    }
+
+    /// Any kind of light. Angles are in degrees, and the colour can be given as a temperature in Kelvin.
+    fn light(&self, statement: &Statement) -> SceneResult<LightRef> {
+        let kind = statement.name.as_str();
+        let mut position = Vec3::default();
+        let mut direction = Vec3::new(0.0, -1.0, 0.0);
+        let mut look_at = None;
+        let mut intensity = 1.0;
+        let mut colour = WHITE;
+        let mut radius = if kind == "sphere_light" { 0.25 } else { 0.0 };
+        let mut angular_radius = 0.0;
+        let (mut inner_angle, mut outer_angle) = (30.0, 45.0);
+
+        for property in statement.block()? {
+            match (kind, property.name.as_str()) {
+                (_, "intensity") => intensity = property.positive_number()?,
+                (_, "colour" | "color") => colour = property.colour()?,
+                (_, "temperature") => {
+                    let kelvin = property.positive_number()?;
+                    if !(1000.0..=40000.0).contains(&kelvin) {
+                        return Err(property.error("`temperature` must be between 1000 and 40000 Kelvin"));
+                    }
+                    colour = kelvin_to_rgb(kelvin);
+                }
+                ("point_light" | "spot_light" | "sphere_light", "position") => position = property.vec3()?,
+                ("directional_light" | "spot_light", "direction") => direction = property.direction()?,
+                ("spot_light", "look_at") => look_at = Some((property, property.vec3()?)),
+                ("spot_light" | "sphere_light", "radius") => radius = property.positive_number()?,
+                ("directional_light", "angle") => angular_radius = property.number()?,
+                ("spot_light", "inner_angle") => inner_angle = property.number()?,
+                ("spot_light", "outer_angle") => outer_angle = property.number()?,
+                _ => return Err(property.unknown(&format!("{} property", kind))),
+            }
+        }
+
+        if let Some((property, target)) = look_at {
+            if target - position == Vec3::default() {
+                return Err(property.error("`look_at` can't be the light's position"));
+            }
+            direction = (target - position).normalized();
+        }
+        if !(0.0..90.0).contains(&angular_radius) {
+            return Err(statement.error("`angle` must be between 0 and 90 degrees"));
+        }
+        if !(0.0 <= inner_angle && inner_angle <= outer_angle && outer_angle < 180.0) {
+            return Err(statement.error("spot light angles need 0 <= inner_angle <= outer_angle < 180"));
+        }
+
+        Ok(match kind {
+            "directional_light" => Box::new(DirectionalLight {
+                direction,
+                intensity,
+                colour,
+                angular_radius: f32::to_radians(angular_radius),
+            }),
+            "spot_light" => Box::new(SpotLight {
+                position,
+                direction,
+                intensity,
+                colour,
+                inner_angle: f32::to_radians(inner_angle),
+                outer_angle: f32::to_radians(outer_angle),
+                radius,
+            }),
+            "sphere_light" => Box::new(SphereLight {
+                position,
+                radius,
+                intensity,
+                colour,
+            }),
+            _ => Box::new(PointLight {
+                position,
+                intensity,
+                colour,
+            }),
+        })
+    }
+
+    /// `texture NAME "PATH" [USCALE VSCALE] [srgb|linear]`. Textures are sRGB unless they say otherwise
+    fn texture(&mut self, statement: &Statement) -> SceneResult<()> {
+        let count = statement.args.len();
+        if statement.block.is_some() || !(2..=5).contains(&count) {
+            return Err(statement.error(
+                "`texture` takes a name, a path, optionally a u and v scale, and optionally `srgb` or `linear`",
+            ));
+        }
+        let name = statement.arg_ident(0)?.to_string();
+        let path = self.base_dir.join(statement.arg_string(1)?);
+        let (uscale, vscale) = match count {
+            4 | 5 => (statement.arg_number(2)?, statement.arg_number(3)?),
+            _ => (1.0, 1.0),
+        };
+        let colour_space = match count {
+            3 | 5 => statement
+                .arg_ident(count - 1)?
+                .parse()
+                .map_err(|e: String| statement.error(e))?,
+            _ => ColourSpace::Srgb,
+        };
+
+        let texture = Texture::new(&path, uscale, vscale, colour_space)
+            .map_err(|e| statement.error(format!("could not load texture {}: {}", path.display(), e)))?;
+        self.textures.insert(name, texture);
+        Ok(())
+    }
+
+    fn material_block(&self, statement: &Statement) -> SceneResult<Material> {
+        let mut material = Material::basic();
+        for property in statement.block()? {
+            let field = match property.name.as_str() {
+                "ior" => {
+                    material.ior = property.positive_number()?;
+                    continue;
+                }
+                // absorption per unit distance for each channel, not a colour
+                "absorption" => {
+                    material.absorption = property.vec3()?;
+                    if material.absorption.min_element() < 0.0 {
+                        return Err(property.error("`absorption` can't be negative"));
+                    }
+                    continue;
+                }
+                "ambient" => &mut material.ambient,
+                "diffuse" => &mut material.diffuse,
+                "specular" => &mut material.specular,
+                "shininess" => &mut material.shininess,
+                "reflectivity" => &mut material.reflectivity,
+                "emissive" => &mut material.emissive,
+                "transmission" => &mut material.transmission,
+                _ => return Err(property.unknown("material property")),
+            };
+            *field = property.number()?;
+        }
+        Ok(material)
+    }
+
+    /// A `material` property of an object: either the name of a material or an inline block
+    fn material(&self, property: &Statement) -> SceneResult<Material> {
+        if property.block.is_some() {
+            if !property.args.is_empty() {
+                return Err(property.error("an inline material does not take a name"));
+            }
+            return self.material_block(property);
+        }
+
+        property.expect_args(1)?;
+        let name = property.arg_ident(0)?;
+        match self.materials.get(name) {
+            Some(material) => Ok(*material),
+            None if name == "basic" => Ok(Material::basic()),
+            None => Err(property.error(format!("unknown material `{}`", name))),
+        }
+    }
+
+    fn texture_ref(&self, property: &Statement) -> SceneResult<&Texture> {
+        property.expect_args(1)?;
+        let name = property.arg_ident(0)?;
+        self.textures
+            .get(name)
+            .ok_or_else(|| property.error(format!("unknown texture `{}`", name)))
+    }
+
+    /// Handle the properties every object has. Returns false if `property` isn't one of them.
+    fn surface_property(
+        &self, property: &Statement, material: &mut Material, colour: &mut Colour,
+    ) -> SceneResult<bool> {
+        match property.name.as_str() {
+            "material" => *material = self.material(property)?,
+            "colour" | "color" => *colour = property.colour()?,
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    /// Any statement in `OBJECTS`
+    fn object(&self, statement: &Statement) -> SceneResult<ObjectRef> {
+        match statement.name.as_str() {
+            "sphere" => Ok(Box::new(self.sphere(statement)?)),
+            "xplane" | "yplane" | "zplane" => self.axis_plane(statement),
+            "union" | "intersection" | "subtraction" => self.csg(statement),
+            "transform" => self.transform(statement),
+            "repeat" | "mirror" | "twist" | "bend" | "elongate" | "round" | "onion" => self.modifier(statement),
+            "mandelbulb" | "menger_sponge" | "sierpinski" | "mandelbox" => self.fractal(statement),
+            _ => self.primitive(statement),
+        }
+    }
+
+    /// `union`, `intersection` and `subtraction` of the objects in the block, in order. A subtraction cuts every other
+    /// object out of the first. `blend RADIUS [polynomial|exponential]` smooths the seams
+    fn csg(&self, statement: &Statement) -> SceneResult<ObjectRef> {
+        let operation = match statement.name.as_str() {
+            "union" => Operation::Union,
+            "intersection" => Operation::Intersection,
+            _ => Operation::Subtraction,
+        };
+        let mut blend = Blend::Sharp;
+        let mut objects = Vec::new();
+
+        for property in statement.block()? {
+            match property.name.as_str() {
+                "blend" => {
+                    let radius = match property.args.len() {
+                        1 | 2 => property.arg_number(0)?,
+                        _ => return Err(property.error("`blend` takes a radius and optionally a kind")),
+                    };
+                    if radius < 0.0 {
+                        return Err(property.error("blend radius can't be negative"));
+                    }
+                    blend = match property.args.get(1).map(|_| property.arg_ident(1)).transpose()? {
+                        None | Some("polynomial") => Blend::Polynomial(radius),
+                        Some("exponential") => Blend::Exponential(radius),
+                        Some(kind) => return Err(property.error(format!("unknown blend `{}`", kind))),
+                    };
+                }
+                name if OBJECTS.contains(&name) => objects.push(self.object(property)?),
+                _ => return Err(property.unknown(&format!("{} property", statement.name))),
+            }
+        }
+
+        let mut objects = objects.into_iter();
+        let (first, second) = match (objects.next(), objects.next()) {
+            (Some(first), Some(second)) => (first, second),
+            _ => return Err(statement.error(format!("`{}` needs at least two objects", statement.name))),
+        };
+        let tree = Csg::new(operation, blend, first, second);
+        Ok(objects.fold(Box::new(tree), |tree, object| {
+            Box::new(Csg::new(operation, blend, tree, object))
+        }))
+    }
+
+    /// `transform` around one object. `translate X Y Z`, `rotate DEGREES AXIS_X AXIS_Y AXIS_Z` and `scale S` or
+    /// `scale X Y Z` apply to the object in the order they're listed
+    fn transform(&self, statement: &Statement) -> SceneResult<ObjectRef> {
+        let mut transform = Mat4::IDENTITY;
+        let mut object = None;
+
+        for property in statement.block()? {
+            let step = match property.name.as_str() {
+                "translate" => Mat4::from_translation(property.vec3()?),
+                "rotate" => {
+                    property.expect_args(4)?;
+                    let angle = property.arg_number(0)?;
+                    let axis = Vec3::new(
+                        property.arg_number(1)?,
+                        property.arg_number(2)?,
+                        property.arg_number(3)?,
+                    );
+                    if axis.mag_sqd() == 0.0 {
+                        return Err(property.error("rotation axis can't be a zero vector"));
+                    }
+                    Mat4::from_quat(Quat::from_axis_angle(axis, angle.to_radians()))
+                }
+                "scale" => {
+                    let scale = match property.args.len() {
+                        1 => {
+                            let scale = property.number()?;
+                            Vec3::new(scale, scale, scale)
+                        }
+                        _ => property.vec3()?,
+                    };
+                    if scale.x() <= 0.0 || scale.y() <= 0.0 || scale.z() <= 0.0 {
+                        return Err(property.error("`scale` must be greater than zero"));
+                    }
+                    Mat4::from_scale(scale)
+                }
+                name if OBJECTS.contains(&name) => {
+                    self.only_object(statement, property, &mut object)?;
+                    continue;
+                }
+                _ => return Err(property.unknown("transform property")),
+            };
+            transform = step * transform;
+        }
+
+        let object = object.ok_or_else(|| statement.error("`transform` needs an object"))?;
+        Ok(Box::new(Transformed::new(object, transform)))
+    }
+
+    /// For statements that wrap a single object
+    fn only_object(
+        &self, statement: &Statement, property: &Statement, object: &mut Option<ObjectRef>,
+    ) -> SceneResult<()> {
+        if object.is_some() {
+            return Err(property.error(format!("`{}` takes one object, put more in a `union`", statement.name)));
+        }
+        *object = Some(self.object(property)?);
+        Ok(())
+    }
+
+    /// Domain modifiers, around one object at the origin. Angles are in degrees, and lengths are whole extents
+    fn modifier(&self, statement: &Statement) -> SceneResult<ObjectRef> {
+        let kind = statement.name.as_str();
+        let mut spacing = Vec3::default();
+        let mut limit = None;
+        let mut axes = (false, false, false);
+        let mut amount = 0.0;
+        let mut length = Vec3::default();
+        let mut object = None;
+
+        let non_negative = |property: &Statement| {
+            let v = property.vec3()?;
+            match v.x() < 0.0 || v.y() < 0.0 || v.z() < 0.0 {
+                true => Err(property.error(format!("`{}` can't be negative", property.name))),
+                false => Ok(v),
+            }
+        };
+
+        for property in statement.block()? {
+            match (kind, property.name.as_str()) {
+                (_, name) if OBJECTS.contains(&name) => self.only_object(statement, property, &mut object)?,
+                ("repeat", "spacing") => spacing = non_negative(property)?,
+                ("repeat", "limit") => {
+                    let v = non_negative(property)?;
+                    if v != Vec3::new(v.x().round(), v.y().round(), v.z().round()) {
+                        return Err(property.error("`limit` must be whole numbers"));
+                    }
+                    limit = Some(v);
+                }
+                ("mirror", "axes") => {
+                    if property.args.is_empty() {
+                        return Err(property.error("`axes` takes some of `x`, `y` and `z`"));
+                    }
+                    for index in 0..property.args.len() {
+                        match property.arg_ident(index)? {
+                            "x" => axes.0 = true,
+                            "y" => axes.1 = true,
+                            "z" => axes.2 = true,
+                            axis => return Err(property.error(format!("unknown axis `{}`", axis))),
+                        }
+                    }
+                }
+                ("twist" | "bend", "rate") => amount = property.number()?.to_radians(),
+                ("elongate", "length") => length = non_negative(property)?,
+                ("round", "radius") | ("onion", "thickness") => amount = property.positive_number()?,
+                _ => return Err(property.unknown(&format!("{} property", kind))),
+            }
+        }
+
+        let object = object.ok_or_else(|| statement.error(format!("`{}` needs an object", kind)))?;
+        let modifier = match kind {
+            "repeat" => match limit {
+                Some(limit) => Modifier::RepeatFinite { spacing, limit },
+                None => Modifier::Repeat { spacing },
+            },
+            "mirror" => Modifier::Mirror {
+                x: axes.0,
+                y: axes.1,
+                z: axes.2,
+            },
+            "twist" => Modifier::Twist { rate: amount },
+            "bend" => Modifier::Bend { rate: amount },
+            "elongate" => Modifier::Elongate {
+                half_extent: length * 0.5,
+            },
+            "round" => Modifier::Round { radius: amount },
+            _ => Modifier::Onion {
+                thickness: amount * 0.5,
+            },
+        };
+        Ok(Box::new(Modified::new(object, modifier)))
+    }
+
+    fn sphere(&self, statement: &Statement) -> SceneResult<Sphere> {
+        let mut sphere = Sphere {
+            position: Vec3::default(),
+            radius:   1.0,
+            material: Material::basic(),
+            colour:   WHITE,
+            lightmap: Default::default(),
+        };
+        for property in statement.block()? {
+            if self.surface_property(property, &mut sphere.material, &mut sphere.colour)? {
+                continue;
+            }
+            match property.name.as_str() {
+                "position" => sphere.position = property.vec3()?,
+                "radius" => sphere.radius = property.positive_number()?,
+                _ => return Err(property.unknown("sphere property")),
+            }
+        }
+        Ok(sphere)
+    }
+
+    /// The solid shapes other than spheres. Sizes and heights are whole extents, centred on `position`
+    fn primitive(&self, statement: &Statement) -> SceneResult<ObjectRef> {
+        let kind = statement.name.as_str();
+        let mut position = Vec3::default();
+        let mut size = Vec3::new(1.0, 1.0, 1.0);
+        let mut radius = match kind {
+            "rounded_box" => 0.1,
+            "capsule" => 0.5,
+            _ => 1.0,
+        };
+        let mut top_radius = 0.0;
+        let mut height = 1.0;
+        let mut minor_radius = 0.25;
+        let mut material = Material::basic();
+        let mut colour = WHITE;
+
+        for property in statement.block()? {
+            if self.surface_property(property, &mut material, &mut colour)? {
+                continue;
+            }
+            match (kind, property.name.as_str()) {
+                (_, "position") => position = property.vec3()?,
+                ("box" | "rounded_box", "size") | ("ellipsoid", "radii") => {
+                    size = property.vec3()?;
+                    if size.x() <= 0.0 || size.y() <= 0.0 || size.z() <= 0.0 {
+                        return Err(property.error(format!("`{}` must be positive", property.name)));
+                    }
+                }
+                ("torus", "major_radius")
+                | ("rounded_box" | "cylinder" | "capsule" | "cone" | "hex_prism" | "octahedron", "radius") => {
+                    radius = property.positive_number()?
+                }
+                ("torus", "minor_radius") => minor_radius = property.positive_number()?,
+                ("cone", "top_radius") => {
+                    top_radius = property.number()?;
+                    if top_radius < 0.0 {
+                        return Err(property.error("`top_radius` can't be negative"));
+                    }
+                }
+                ("cylinder" | "cone" | "hex_prism", "height") | ("capsule", "length") => {
+                    height = property.positive_number()?
+                }
+                _ => return Err(property.unknown(&format!("{} property", kind))),
+            }
+        }
+
+        let half_size = size * 0.5;
+        let lightmap = Default::default();
+        Ok(match kind {
+            "box" => Box::new(Cuboid {
+                position,
+                half_size,
+                material,
+                colour,
+                lightmap,
+            }),
+            "rounded_box" => {
+                if radius > half_size.x().min(half_size.y()).min(half_size.z()) {
+                    return Err(statement.error("rounded box `radius` can't be more than half its smallest side"));
+                }
+                Box::new(RoundedBox {
+                    position,
+                    half_size,
+                    radius,
+                    material,
+                    colour,
+                    lightmap,
+                })
+            }
+            "torus" => Box::new(Torus {
+                position,
+                major_radius: radius,
+                minor_radius,
+                material,
+                colour,
+                lightmap,
+            }),
+            "cylinder" => Box::new(Cylinder {
+                position,
+                radius,
+                half_height: height * 0.5,
+                material,
+                colour,
+                lightmap,
+            }),
+            "capsule" => Box::new(Capsule {
+                position,
+                radius,
+                half_height: height * 0.5,
+                material,
+                colour,
+                lightmap,
+            }),
+            "cone" => Box::new(Cone {
+                position,
+                radius,
+                top_radius,
+                half_height: height * 0.5,
+                material,
+                colour,
+                lightmap,
+            }),
+            "ellipsoid" => Box::new(Ellipsoid {
+                position,
+                radii: size,
+                material,
+                colour,
+                lightmap,
+            }),
+            "hex_prism" => Box::new(HexPrism {
+                position,
+                radius,
+                half_height: height * 0.5,
+                material,
+                colour,
+                lightmap,
+            }),
+            _ => Box::new(Octahedron {
+                position,
+                radius,
+                material,
+                colour,
+                lightmap,
+            }),
+        })
+    }
+
+    /// `mandelbulb`, `menger_sponge`, `sierpinski` and `mandelbox`. `trap_colour` is where the orbit trap is closest
+    fn fractal(&self, statement: &Statement) -> SceneResult<ObjectRef> {
+        let kind = statement.name.as_str();
+        let mut position = Vec3::default();
+        let mut size = 1.0;
+        let (mut iterations, mut bailout, mut power) = match kind {
+            "mandelbulb" => (8, 2.0, 8.0),
+            "menger_sponge" => (5, 1.0, 3.0),
+            "sierpinski" => (10, 4.0, 2.0),
+            _ => (12, 16.0, 2.0),
+        };
+        let mut material = Material::basic();
+        let mut colour = WHITE;
+        let mut trap_colour = SOFT_RED;
+
+        for property in statement.block()? {
+            if self.surface_property(property, &mut material, &mut colour)? {
+                continue;
+            }
+            match property.name.as_str() {
+                "position" => position = property.vec3()?,
+                "size" => size = property.positive_number()?,
+                "iterations" => iterations = property.count()?,
+                "bailout" => bailout = property.positive_number()?,
+                "power" => {
+                    power = property.number()?;
+                    let valid = match kind {
+                        "mandelbox" => power.abs() > 1.0,
+                        _ => power > 1.0,
+                    };
+                    if !valid {
+                        return Err(property.error(match kind {
+                            "mandelbox" => "mandelbox `power` must be more than 1 or less than -1",
+                            _ => "`power` must be more than 1",
+                        }));
+                    }
+                }
+                "trap_colour" | "trap_color" => trap_colour = property.colour()?,
+                _ => return Err(property.unknown(&format!("{} property", kind))),
+            }
+        }
+
+        let lightmap = Default::default();
+        Ok(match kind {
+            "mandelbulb" => Box::new(Mandelbulb {
+                position,
+                size,
+                iterations,
+                bailout,
+                power,
+                material,
+                colour,
+                trap_colour,
+                lightmap,
+            }),
+            "menger_sponge" => Box::new(MengerSponge {
+                position,
+                size,
+                iterations,
+                bailout,
+                power,
+                material,
+                colour,
+                trap_colour,
+                lightmap,
+            }),
+            "sierpinski" => Box::new(SierpinskiTetrahedron {
+                position,
+                size,
+                iterations,
+                bailout,
+                power,
+                material,
+                colour,
+                trap_colour,
+                lightmap,
+            }),
+            _ => Box::new(Mandelbox {
+                position,
+                size,
+                iterations,
+                bailout,
+                power,
+                material,
+                colour,
+                trap_colour,
+                lightmap,
+            }),
+        })
+    }
+
+    /// `xplane`, `yplane` and `zplane`: an offset along the axis, and which way the plane faces
+    fn axis_plane(&self, statement: &Statement) -> SceneResult<ObjectRef> {
+        let axis = &statement.name[..1];
+        let mut offset = 0.0;
+        let mut dir = 1.0;
+        let mut material = Material::basic();
+        let mut colour = WHITE;
+        let mut texture = None;
+
+        for property in statement.block()? {
+            if self.surface_property(property, &mut material, &mut colour)? {
+                continue;
+            }
+            match property.name.as_str() {
+                name if name == axis => offset = property.number()?,
+                "dir" => {
+                    dir = property.number()?;
+                    if dir != 1.0 && dir != -1.0 {
+                        return Err(property.error("`dir` must be 1 or -1"));
+                    }
+                }
+                "texture" if axis == "y" => texture = Some(self.texture_ref(property)?),
+                _ => return Err(property.unknown(&format!("{} property", statement.name))),
+            }
+        }
+
+        Ok(match axis {
+            "x" => Box::new(XPlane::new(offset, dir, material, colour)),
+            "y" => Box::new(YPlane::new(offset, dir, material, colour, texture)),
+            _ => Box::new(ZPlane::new(offset, dir, material, colour)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampling::{ReconstructionFilter, SamplePattern};
+
+    fn parse(source: &str) -> Engine {
+        match parse_scene(source, Path::new("")) {
+            Ok(engine) => engine,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// The line, column and message of the error `source` fails with
+    fn parse_error(source: &str) -> (usize, usize, String) {
+        match parse_scene(source, Path::new("")) {
+            Err(SceneError::Parse { line, column, message }) => (line, column, message),
+            Err(e) => panic!("expected a parse error, got {}", e),
+            Ok(_) => panic!("expected a parse error"),
+        }
+    }
+
+    fn xyz(v: Vec3) -> (f32, f32, f32) { (v.x(), v.y(), v.z()) }
+
+    #[test]
+    fn default_scene() {
+        let engine = load_scene(DEFAULT_SCENE).unwrap();
+        assert_eq!(xyz(engine.camera.position), (0.0, 0.5, -3.5));
+        assert_eq!(engine.objects.len(), 8);
+    }
+
+    #[test]
+    fn several_lights() {
+        let engine = parse("point_light { position 0 1 0 }\npoint_light { position 2 1 0 }\npoint_light {}");
+        assert_eq!(engine.lights.len(), 3);
+        assert_eq!(xyz(engine.lights[1].get_position()), (2.0, 1.0, 0.0));
+
+        // a scene with no lights is only lit by its ambient and emissive materials
+        assert!(parse("sphere {}").lights.is_empty());
+    }
+
+    #[test]
+    fn snippets() {
+        let engine = parse(
+            "# a comment\n\
+             camera { position 1 2 3 }   # trailing comment\n\
+             material shiny { reflectivity 0.5 }\n\
+             sphere { radius 0.5; material shiny; colour soft_red }\n\
+             \n\
+             yplane { y -2; dir 1 }\n\
+             point_light { position 0 1 0 }",
+        );
+        assert_eq!(xyz(engine.camera.position), (1.0, 2.0, 3.0));
+        assert_eq!(engine.objects.len(), 2);
+        assert_eq!(engine.objects[0].material().reflectivity, 0.5);
+    }
+
+    #[test]
+    fn csg_block() {
+        let engine = parse(
+            "subtraction {\n\
+                 union { blend 0.3 exponential; sphere {}; box { size 1 2 1 }; sphere { position 0 1 0 } }\n\
+                 sphere { radius 0.5 }\n\
+             }\n\
+             point_light {}",
+        );
+        assert_eq!(engine.objects.len(), 1);
+        assert_eq!(engine.objects[0].sdf(Vec3::default()), 0.5);
+
+        let (line, column, message) = parse_error("union {\n    sphere {}\n}");
+        assert_eq!((line, column), (1, 1));
+        assert_eq!(message, "`union` needs at least two objects");
+    }
+
+    #[test]
+    fn camera_block() {
+        let camera = parse("camera { position 1 0 0; look_at 2 0 0; pitch 10; fov 60 }\npoint_light {}").camera;
+        assert_eq!(xyz(camera.position), (1.0, 0.0, 0.0));
+        assert!((camera.yaw - 90f32.to_radians()).abs() < 1e-6);
+        assert!((camera.pitch - 10f32.to_radians()).abs() < 1e-6);
+        assert_eq!(camera.fov, 60.0);
+
+        let (line, column, message) = parse_error("camera {\n    fov 180\n}");
+        assert_eq!((line, column), (2, 5));
+        assert_eq!(message, "`fov` must be less than 180 degrees");
+    }
+
+    #[test]
+    fn material_block() {
+        let engine = parse(
+            "material glass { ior 1.5; transmission 0.9; absorption 0.1 0 0.2; reflectivity 0 }\n\
+             sphere { material glass }",
+        );
+        let material = engine.objects[0].material();
+        assert_eq!((material.ior, material.transmission), (1.5, 0.9));
+        assert_eq!(xyz(material.absorption), (0.1, 0.0, 0.2));
+
+        let (_, _, message) = parse_error("material water { ior 0 }");
+        assert_eq!(message, "`ior` must be greater than zero");
+        let (_, _, message) = parse_error("material water { absorption 0.1 -0.1 0 }");
+        assert_eq!(message, "`absorption` can't be negative");
+    }
+
+    #[test]
+    fn unknown_block() {
+        let (line, column, message) = parse_error("sky 0 0 0\n  teapot { size 1 }");
+        assert_eq!((line, column), (2, 3));
+        assert!(message.contains("unknown statement `teapot`"), "{}", message);
+    }
+
+    #[test]
+    fn supersampling_block() {
+        let ssaa = parse("supersampling { samples 9; pattern stratified; filter tent }").ssaa;
+        assert_eq!(ssaa.samples, 9);
+        assert_eq!(ssaa.pattern, SamplePattern::Stratified);
+        assert_eq!(ssaa.filter, ReconstructionFilter::Tent);
+
+        let (_, _, message) = parse_error("supersampling { filter gaussian }");
+        assert_eq!(message, "unknown filter `gaussian`, expected box, tent or mitchell");
+    }
+
+    #[test]
+    fn post_block() {
+        let post = parse(
+            "post {\n\
+                 exposure 2\n\
+                 tonemap aces\n\
+                 vignette { strength 0.5; enabled off }\n\
+                 output gamma 2.2\n\
+                 fxaa { span_max 4 }\n\
+             }",
+        )
+        .post;
+        let names: Vec<_> = post.stages.iter().map(|stage| stage.pass.name()).collect();
+        assert_eq!(names, ["exposure", "tonemap", "vignette", "output", "fxaa"]);
+        let enabled: Vec<_> = post.stages.iter().map(|stage| stage.enabled).collect();
+        assert_eq!(enabled, [true, true, false, true, true]);
+
+        let (_, _, message) = parse_error("post { output gamma }");
+        assert_eq!(message, "`output` takes `srgb`, `linear`, or `gamma` and a value");
+        let (_, _, message) = parse_error("post { tonemap filmic }");
+        assert_eq!(
+            message,
+            "unknown tonemapper `filmic`, expected aces, reinhard, hable, agx or clamp"
+        );
+        let (_, _, message) = parse_error("post { tonemap reinhard -1 }");
+        assert_eq!(message, "the white point must be greater than zero");
+        let (_, _, message) = parse_error("post { grade { saturation -0.5 } }");
+        assert_eq!(message, "`saturation` can't be negative");
+        let (_, _, message) = parse_error("post { bloom { intensity -1 } }");
+        assert_eq!(message, "`intensity` can't be negative");
+        let (_, _, message) = parse_error("post { vignette { radius 1.5 } }");
+        assert_eq!(message, "`radius` must be between 0 and 1");
+        let (_, _, message) = parse_error("post { sharpen { amount -2 } }");
+        assert_eq!(message, "`amount` can't be negative");
+        let (_, _, message) = parse_error("post { fxaa { reduce_mul -1 } }");
+        assert_eq!(message, "`reduce_mul` can't be negative");
+    }
+
+    #[test]
+    fn auto_exposure_block() {
+        let post = parse("post { auto_exposure { key 0.2; low 0.1; high 0.9; speed 3 }; tonemap reinhard 4 }").post;
+        assert_eq!(post.stages[0].pass.name(), "auto_exposure");
+        assert_eq!(post.stages.len(), 2);
+
+        let (_, _, message) = parse_error("post { auto_exposure { low 0.9; high 0.5 } }");
+        assert_eq!(
+            message,
+            "auto_exposure needs low < high and min_exposure <= max_exposure"
+        );
+        let (_, _, message) = parse_error("post { auto_exposure { low 0.9; high 1.2 } }");
+        assert_eq!(message, "`high` must be between 0 and 1");
+    }
+
+    #[test]
+    fn colour_spaces() {
+        let post = parse("post { output srgb }").post;
+        assert_eq!(post.stages[0].pass.name(), "output");
+
+        let (_, _, message) = parse_error("texture wood \"wood.bmp\" 1 1 cmyk");
+        assert_eq!(message, "unknown colour space `cmyk`, expected srgb or linear");
+    }
+
+    #[test]
+    fn wrong_argument_count() {
+        let (line, column, message) = parse_error("camera {\n    position 0 1\n}");
+        assert_eq!((line, column), (2, 5));
+        assert_eq!(message, "`position` takes 3 values, found 2");
+    }
+
+    #[test]
+    fn march_block() {
+        let march = parse("march { max_steps 100; relaxation 1.5; epsilon 0.01; pixel_footprint off }").march;
+        assert_eq!(march.max_steps, 100);
+        assert_eq!(march.relaxation, 1.5);
+        assert_eq!(march.hit_epsilon, 0.01);
+        assert!(!march.pixel_footprint);
+
+        let (line, column, message) = parse_error("march {\n    relaxation 2.5\n}");
+        assert_eq!((line, column), (2, 5));
+        assert_eq!(message, "`relaxation` must be between 1 and 2");
+        let (_, _, message) = parse_error("march { epsilon_slope -0.001 }");
+        assert_eq!(message, "`epsilon_slope` can't be negative");
+    }
+
+    #[test]
+    fn bounces_block() {
+        let bounces = parse("bounces { max_depth 3; min_throughput 0.05; russian_roulette off }").bounces;
+        assert_eq!(bounces.max_depth, 3);
+        assert_eq!(bounces.min_throughput, 0.05);
+        assert!(!bounces.russian_roulette);
+
+        let (_, _, message) = parse_error("bounces { russian_roulette maybe }");
+        assert_eq!(message, "`russian_roulette` must be `on` or `off`");
+        let (_, _, message) = parse_error("bounces { min_throughput -0.1 }");
+        assert_eq!(message, "`min_throughput` can't be negative");
+    }
+
+    #[test]
+    fn lights() {
+        let engine = parse(
+            "directional_light { direction 0 -1 0; angle 2 }\n\
+             spot_light { position 0 2 0; look_at 0 0 0; inner_angle 20; outer_angle 30 }\n\
+             sphere_light { position 1 1 1; radius 0.5; intensity 2 }",
+        );
+        assert_eq!(engine.lights.len(), 3);
+
+        let (_, _, message) = parse_error("spot_light { inner_angle 40; outer_angle 30 }");
+        assert_eq!(message, "spot light angles need 0 <= inner_angle <= outer_angle < 180");
+        let (_, _, message) = parse_error("point_light { intensity -2 }");
+        assert_eq!(message, "`intensity` must be greater than zero");
+    }
+
+    #[test]
+    fn light_colours() {
+        let engine = parse(
+            "point_light { colour 255 0 0 }\n\
+             point_light { temperature 6600 }\n\
+             spot_light { temperature 2000 }\n\
+             directional_light {}",
+        );
+        let colours: Vec<_> = engine.lights.iter().map(|light| xyz(light.get_colour())).collect();
+        assert_eq!(colours[0], (1.0, 0.0, 0.0));
+        assert_eq!(colours[1], (1.0, 1.0, 1.0));
+        // candlelight is orange
+        let (r, g, b) = colours[2];
+        assert!(r == 1.0 && g < r && b < g, "{:?}", colours[2]);
+        assert_eq!(colours[3], xyz(WHITE));
+
+        let (_, _, message) = parse_error("point_light { temperature 500 }");
+        assert_eq!(message, "`temperature` must be between 1000 and 40000 Kelvin");
+    }
+
+    #[test]
+    fn zero_direction() {
+        let (line, column, message) = parse_error("directional_light { direction 0 0 0 }");
+        assert_eq!((line, column), (1, 21));
+        assert_eq!(message, "`direction` can't be a zero vector");
+    }
+
+    #[test]
+    fn look_at_position() {
+        let (line, column, message) = parse_error("camera { position 1 2 3; look_at 1 2 3 }");
+        assert_eq!((line, column), (1, 26));
+        assert_eq!(message, "`look_at` can't be the camera's position");
+    }
+
+    #[test]
+    fn spot_light_look_at_position() {
+        let (line, column, message) = parse_error("spot_light {\n    position 0 1 0\n    look_at 0 1 0\n}");
+        assert_eq!((line, column), (3, 5));
+        assert_eq!(message, "`look_at` can't be the light's position");
+    }
+
+    #[test]
+    fn whole_numbers() {
+        let (line, column, message) = parse_error("march { max_steps 0.5 }");
+        assert_eq!((line, column), (1, 9));
+        assert_eq!(message, "`max_steps` must be a whole number, zero or more");
+
+        let (_, _, message) = parse_error("march { max_steps 0 }");
+        assert_eq!(message, "`max_steps` must be at least 1");
+
+        let (_, column, message) = parse_error("march { refine_steps -1 }");
+        assert_eq!(column, 9);
+        assert_eq!(message, "`refine_steps` must be a whole number, zero or more");
+
+        assert_eq!(parse("march { refine_steps 0 }").march.refine_steps, 0);
+
+        let (_, _, message) = parse_error("bounces { max_depth 1.5 }");
+        assert_eq!(message, "`max_depth` must be a whole number, zero or more");
+        assert_eq!(parse("bounces { max_depth 0 }").bounces.max_depth, 0);
+
+        let (_, _, message) = parse_error("supersampling { samples 0.5 }");
+        assert_eq!(message, "`samples` must be a whole number, zero or more");
+
+        let (_, _, message) = parse_error("mandelbulb { iterations 0.5 }");
+        assert_eq!(message, "`iterations` must be a whole number, zero or more");
+    }
+
+    #[test]
+    fn non_positive_number() {
+        let (line, column, message) = parse_error("sphere { position 0 0 0; radius -1 }");
+        assert_eq!((line, column), (1, 26));
+        assert_eq!(message, "`radius` must be greater than zero");
+    }
+}
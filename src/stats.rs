@@ -0,0 +1,254 @@
+//! Frame timing. Every frame records how long it spent in each stage of rendering, and a `Profiler` keeps the most
+//! recent frames for averages and percentiles, optionally logging every frame to a CSV file.
+
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::ops::{AddAssign, Index, IndexMut};
+use std::path::Path;
+use std::time::Duration;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stage {
+    /// building the table of ray directions
+    Directions,
+    /// marching sight rays to the first thing they hit
+    March,
+    /// lighting the first hit, not counting its shadows or bounces
+    Shading,
+    /// shadow rays from the first hit
+    Shadows,
+    /// everything reflection and refraction rays do
+    Reflections,
+    /// filtering supersamples and accumulating frames
+    Resolve,
+    /// post-processing, from tonemapping to the output transfer
+    Post,
+    /// writing the finished pixels out, and anything the caller adds to show them
+    Blit,
+}
+
+pub const STAGES: [Stage; 8] = [
+    Stage::Directions,
+    Stage::March,
+    Stage::Shading,
+    Stage::Shadows,
+    Stage::Reflections,
+    Stage::Resolve,
+    Stage::Post,
+    Stage::Blit,
+];
+
+impl Stage {
+    pub fn name(self) -> &'static str {
+        match self {
+            Stage::Directions => "directions",
+            Stage::March => "march",
+            Stage::Shading => "shading",
+            Stage::Shadows => "shadows",
+            Stage::Reflections => "reflections",
+            Stage::Resolve => "resolve",
+            Stage::Post => "post",
+            Stage::Blit => "blit",
+        }
+    }
+}
+
+/// Time spent in each stage
+#[derive(Clone, Copy, Debug, Default)]
+pub struct StageTimes([Duration; STAGES.len()]);
+
+impl StageTimes {
+    pub fn total(&self) -> Duration { self.0.iter().sum() }
+}
+
+impl Index<Stage> for StageTimes {
+    type Output = Duration;
+
+    fn index(&self, stage: Stage) -> &Duration { &self.0[stage as usize] }
+}
+
+impl IndexMut<Stage> for StageTimes {
+    fn index_mut(&mut self, stage: Stage) -> &mut Duration { &mut self.0[stage as usize] }
+}
+
+impl AddAssign for StageTimes {
+    fn add_assign(&mut self, other: Self) {
+        for (a, b) in self.0.iter_mut().zip(other.0) {
+            *a += b;
+        }
+    }
+}
+
+fn millis(duration: Duration) -> f64 { duration.as_secs_f64() * 1000.0 }
+
+/// Rolling timings over the last few frames
+pub struct Profiler {
+    /// stage times and the whole frame time of each recorded frame, oldest first
+    frames:   VecDeque<(StageTimes, Duration)>,
+    capacity: usize,
+    recorded: u64,
+    csv:      Option<BufWriter<File>>,
+}
+
+impl Profiler {
+    /// Keep the last `capacity` frames
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            frames:   VecDeque::with_capacity(capacity),
+            capacity: capacity.max(1),
+            recorded: 0,
+            csv:      None,
+        }
+    }
+
+    /// Also write every frame recorded from now on to a CSV file, in milliseconds
+    pub fn log_csv<P: AsRef<Path>>(&mut self, path: P) -> io::Result<()> {
+        let mut csv = BufWriter::new(File::create(path)?);
+        write!(csv, "frame")?;
+        for stage in STAGES {
+            write!(csv, ",{}_ms", stage.name())?;
+        }
+        writeln!(csv, ",frame_ms")?;
+        self.csv = Some(csv);
+        Ok(())
+    }
+
+    /// Add a frame's stage times, and how long the whole frame took. If writing the CSV fails it's closed and the
+    /// error returned
+    pub fn record(&mut self, times: StageTimes, frame: Duration) -> io::Result<()> {
+        if self.frames.len() == self.capacity {
+            self.frames.pop_front();
+        }
+        self.frames.push_back((times, frame));
+        self.recorded += 1;
+
+        let recorded = self.recorded;
+        if let Some(csv) = &mut self.csv {
+            let mut write_row = || {
+                write!(csv, "{}", recorded)?;
+                for stage in STAGES {
+                    write!(csv, ",{:.3}", millis(times[stage]))?;
+                }
+                writeln!(csv, ",{:.3}", millis(frame))?;
+                csv.flush()
+            };
+            if let Err(e) = write_row() {
+                self.csv = None;
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Table of the mean and percentiles of every stage over the frames kept
+    pub fn summary(&self) -> String {
+        let count = self.frames.len();
+        let frame_total: Duration = self.frames.iter().map(|(_, frame)| *frame).sum();
+        let mut summary = format!(
+            "{} frames, {:.1} fps\n{:<12} {:>9} {:>9} {:>9} {:>9}\n",
+            count,
+            count as f64 / frame_total.as_secs_f64().max(f64::EPSILON),
+            "stage",
+            "mean",
+            "p50",
+            "p95",
+            "p99"
+        );
+
+        let mut row = |name: &str, mut values: Vec<Duration>| {
+            values.sort();
+            let mean = values.iter().sum::<Duration>() / values.len().max(1) as u32;
+            // nearest rank
+            let percentile = |p: f64| values.get(((values.len() - 1) as f64 * p).round() as usize).copied();
+            let p = |p: f64| millis(percentile(p).unwrap_or_default());
+            summary += &format!(
+                "{:<12} {:>7.2}ms {:>7.2}ms {:>7.2}ms {:>7.2}ms\n",
+                name,
+                millis(mean),
+                p(0.5),
+                p(0.95),
+                p(0.99)
+            );
+        };
+        if count > 0 {
+            for stage in STAGES {
+                row(
+                    stage.name(),
+                    self.frames.iter().map(|(times, _)| times[stage]).collect(),
+                );
+            }
+            row("frame", self.frames.iter().map(|(_, frame)| *frame).collect());
+        }
+        summary
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn ms(n: u64) -> Duration { Duration::from_millis(n) }
+
+    /// A frame that spent `march` in marching and 1ms writing pixels
+    fn times(march: Duration) -> StageTimes {
+        let mut times = StageTimes::default();
+        times[Stage::March] = march;
+        times[Stage::Blit] = ms(1);
+        times
+    }
+
+    #[test]
+    fn stage_times() {
+        let mut total = times(ms(3));
+        total += times(ms(4));
+        assert_eq!(total[Stage::March], ms(7));
+        assert_eq!(total[Stage::Blit], ms(2));
+        assert_eq!(total[Stage::Shading], Duration::ZERO);
+        assert_eq!(total.total(), ms(9));
+
+        for (i, stage) in STAGES.iter().enumerate() {
+            assert_eq!(*stage as usize, i);
+        }
+    }
+
+    #[test]
+    fn rolling_percentiles() {
+        let mut profiler = Profiler::new(100);
+        // the first few fall out of the window
+        for n in (1..=100).rev().chain(1..=100) {
+            profiler.record(times(ms(n)), ms(n + 1)).unwrap();
+        }
+        let summary = profiler.summary();
+        assert!(summary.starts_with("100 frames"), "{}", summary);
+
+        let march = summary.lines().find(|line| line.starts_with("march")).unwrap();
+        assert_eq!(march, "march          50.50ms   51.00ms   95.00ms   99.00ms");
+        let blit = summary.lines().find(|line| line.starts_with("blit")).unwrap();
+        assert_eq!(blit, "blit            1.00ms    1.00ms    1.00ms    1.00ms");
+
+        assert_eq!(Profiler::new(10).summary().lines().count(), 2);
+    }
+
+    #[test]
+    fn csv_log() {
+        let path = std::env::temp_dir().join(format!("{}-timings.csv", std::process::id()));
+        let mut profiler = Profiler::new(1);
+        profiler.record(times(ms(5)), ms(6)).unwrap();
+        profiler.log_csv(&path).unwrap();
+        profiler.record(times(ms(2)), ms(3)).unwrap();
+        drop(profiler);
+
+        let csv = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let lines: Vec<_> = csv.lines().collect();
+        assert_eq!(
+            lines,
+            [
+                "frame,directions_ms,march_ms,shading_ms,shadows_ms,reflections_ms,resolve_ms,post_ms,blit_ms,frame_ms",
+                "2,0.000,2.000,0.000,0.000,0.000,0.000,0.000,1.000,3.000",
+            ]
+        );
+    }
+}
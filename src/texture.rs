@@ -1,31 +1,62 @@
+use std::path::Path;
+use std::str::FromStr;
+
+use image::ImageResult;
+
 use super::colour::Colour;
 
+/// How the values in an image file are encoded
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColourSpace {
+    /// colour images, decoded to linear when loaded
+    Srgb,
+    /// data like normal or roughness maps, loaded as they are
+    Linear,
+}
+
+impl FromStr for ColourSpace {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "srgb" => Ok(ColourSpace::Srgb),
+            "linear" => Ok(ColourSpace::Linear),
+            _ => Err(format!("unknown colour space `{}`, expected srgb or linear", s)),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Texture {
-    image:      Vec<Colour>,
-    width:      u32,
-    height:     u32,
-    pub uscale: f32,
-    pub vscale: f32,
+    image:            Vec<Colour>,
+    width:            u32,
+    height:           u32,
+    pub uscale:       f32,
+    pub vscale:       f32,
+    pub colour_space: ColourSpace,
 }
 
 impl Texture {
-    pub fn new(path: &str, uscale: f32, vscale: f32) -> Self {
-        let raw_image = image::open(path).unwrap().into_rgb8();
+    pub fn new<P: AsRef<Path>>(path: P, uscale: f32, vscale: f32, colour_space: ColourSpace) -> ImageResult<Self> {
+        let raw_image = image::open(path)?.into_rgb8();
         let (width, height) = (raw_image.width(), raw_image.height());
         let mut image = Vec::with_capacity((raw_image.width() * raw_image.height()) as usize);
 
         for pixel in raw_image.pixels() {
             let [r, g, b] = pixel.0;
-            image.push(rgb!(r, g, b))
+            image.push(match colour_space {
+                ColourSpace::Srgb => rgb!(r, g, b),
+                ColourSpace::Linear => Colour::new(r as f32, g as f32, b as f32) / 255.0,
+            })
         }
-        Self {
+        Ok(Self {
             image,
             width,
             height,
             uscale,
             vscale,
-        }
+            colour_space,
+        })
     }
 
     pub fn sample(&self, u: f32, v: f32) -> Colour {
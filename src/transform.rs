@@ -0,0 +1,160 @@
+//! Moving, rotating and scaling objects. `Transformed` evaluates the object it wraps in its own local space, so any
+//! object can be turned or stretched without storing an orientation itself.
+
+use crate::bvh::Aabb;
+use crate::colour::Colour;
+use crate::material::Material;
+use crate::objects::EngineObject;
+use crate::radiosity::Lightmap;
+use crate::vector::{Mat3, Mat4, Quat, Vec3};
+
+pub struct Transformed<T: EngineObject> {
+    pub object:    T,
+    /// local space to world space
+    transform:     Mat4,
+    /// world space to local space
+    inverse:       Mat4,
+    /// takes local normals to world normals
+    normal_matrix: Mat3,
+    /// the most the transform shrinks any distance by. local distances are scaled by this so they never overestimate
+    min_scale:     f32,
+}
+
+impl<T: EngineObject> Transformed<T> {
+    /// `transform` takes the object's space to world space, and mustn't squash it flat
+    pub fn new(object: T, transform: Mat4) -> Self {
+        let inverse = transform.inverse();
+        Self {
+            object,
+            transform,
+            inverse,
+            normal_matrix: inverse.mat3().transpose(),
+            min_scale: transform.mat3().scale_bounds().0,
+        }
+    }
+
+    /// Scale, then rotate, then translate
+    pub fn from_scale_rotation_translation(object: T, scale: Vec3, rotation: Quat, translation: Vec3) -> Self {
+        Self::new(
+            object,
+            Mat4::from_scale_rotation_translation(scale, rotation, translation),
+        )
+    }
+
+    pub fn transform(&self) -> Mat4 { self.transform }
+
+    fn local(&self, position: Vec3) -> Vec3 { self.inverse.transform_point(position) }
+}
+
+impl<T: EngineObject> EngineObject for Transformed<T> {
+    // exact for rotations and uniform scale, a lower bound when stretched more one way than another
+    fn sdf(&self, position: Vec3) -> f32 { self.object.sdf(self.local(position)) * self.min_scale }
+
+    fn bounds(&self) -> Option<Aabb> {
+        let local = self.object.bounds()?;
+        let corners = (0..8).map(|i| {
+            let pick = |bit: usize, min: f32, max: f32| if i & bit == 0 { min } else { max };
+            Vec3::new(
+                pick(1, local.min.x(), local.max.x()),
+                pick(2, local.min.y(), local.max.y()),
+                pick(4, local.min.z(), local.max.z()),
+            )
+        });
+        corners
+            .map(|corner| self.transform.transform_point(corner))
+            .map(|corner| Aabb::new(corner, corner))
+            .reduce(|a, b| a.union(&b))
+    }
+
+    fn colour(&self, position: Vec3) -> Colour { self.object.colour(self.local(position)) }
+    fn material(&self) -> &Material { self.object.material() }
+    fn material_at(&self, position: Vec3) -> Material { self.object.material_at(self.local(position)) }
+
+    fn radiosity_collide(&self) -> bool { self.object.radiosity_collide() }
+
+    // the object keeps its own lightmap, laid out in its local space
+    fn get_lightmap(&self) -> Option<&Lightmap> { self.object.get_lightmap() }
+    fn set_lightmap(&mut self, new_lightmap: Lightmap) { self.object.set_lightmap(new_lightmap) }
+    fn clear_lightmap(&mut self) { self.object.clear_lightmap() }
+
+    fn get_sample_pos(&self, u: usize, v: usize) -> Vec3 {
+        self.transform.transform_point(self.object.get_sample_pos(u, v))
+    }
+    fn sample_uv_from_pos(&self, pos: Vec3) -> (f32, f32) { self.object.sample_uv_from_pos(self.local(pos)) }
+    fn sample_lightmap(&self, pos: Vec3) -> Colour { self.object.sample_lightmap(self.local(pos)) }
+
+    fn calculate_normal(&self, position: Vec3) -> Vec3 {
+        (self.normal_matrix * self.object.calculate_normal(self.local(position))).normalized()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::colour::WHITE;
+    use crate::objects::Sphere;
+
+    fn unit_sphere() -> Sphere {
+        Sphere {
+            position: Vec3::default(),
+            radius:   1.0,
+            material: Material::basic(),
+            colour:   WHITE,
+            lightmap: Default::default(),
+        }
+    }
+
+    fn points() -> impl Iterator<Item = Vec3> {
+        (0..9 * 9 * 9)
+            .map(|i| Vec3::new((i % 9) as f32, (i / 9 % 9) as f32, (i / 81) as f32) * 1.1 - Vec3::new(4.0, 4.0, 4.0))
+    }
+
+    #[test]
+    fn uniform_scale() {
+        let rotation = Quat::from_axis_angle(Vec3::new(1.0, 2.0, 0.5), 0.8);
+        let translation = Vec3::new(1.0, -0.5, 2.0);
+        let sphere = Transformed::from_scale_rotation_translation(
+            unit_sphere(),
+            Vec3::new(2.0, 2.0, 2.0),
+            rotation,
+            translation,
+        );
+        for position in points() {
+            let expected = (position - translation).mag() - 2.0;
+            assert!((sphere.sdf(position) - expected).abs() < 1e-4, "at {}", position);
+        }
+
+        let bounds = sphere.bounds().unwrap();
+        assert!(bounds.distance(translation + Vec3::new(0.0, 0.0, 2.0)) <= 0.0);
+    }
+
+    #[test]
+    fn non_uniform_scale() {
+        let scale = Vec3::new(1.0, 2.0, 3.0);
+        let rotation = Quat::from_axis_angle(Vec3::new(0.0, 1.0, 1.0), 0.5);
+        let ellipsoid = Transformed::from_scale_rotation_translation(unit_sphere(), scale, rotation, Vec3::default());
+
+        // never more than the distance to the surface, so nothing closer than it says can be hit
+        let samples = points().collect::<Vec<_>>();
+        for (&a, &b) in samples.iter().zip(samples.iter().skip(37)) {
+            assert!((ellipsoid.sdf(a) - ellipsoid.sdf(b)).abs() <= (a - b).mag() + 1e-4);
+        }
+        // along the longest axis it is still a bound
+        let axis = rotation.rotate(Vec3::new(0.0, 0.0, 1.0));
+        assert!(ellipsoid.sdf(axis * 5.0) <= 2.0 + 1e-4);
+        assert!(ellipsoid.sdf(axis * 2.0) < 0.0);
+
+        // normals are the gradient of the ellipsoid x²/a² + y²/b² + z²/c², not the stretched sphere normal
+        for direction in [
+            Vec3::new(1.0, 1.0, 0.0),
+            Vec3::new(0.0, 1.0, 1.0),
+            Vec3::new(1.0, -1.0, 1.0),
+        ] {
+            let local = direction.normalized();
+            let surface = rotation.rotate(local.element_mul(scale));
+            let expected = rotation.rotate(local.element_div(scale).normalized());
+            let normal = ellipsoid.calculate_normal(surface);
+            assert!((normal - expected).mag() < 1e-3, "{} != {}", normal, expected);
+        }
+    }
+}
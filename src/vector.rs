@@ -1,9 +1,11 @@
+use std::f32::consts::TAU;
 use std::fmt;
 use std::intrinsics::sqrtf32;
-use std::ops::{Add, AddAssign, Div, Mul, Neg, Sub};
+use std::ops::{Add, AddAssign, Div, Mul, Neg, Sub, SubAssign};
+use std::simd::num::SimdFloat;
 use std::simd::{f32x4, StdFloat};
 
-#[derive(Debug, Default, Clone, Copy)]
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
 #[repr(transparent)]
 pub struct Vec3(pub f32x4);
 
@@ -31,10 +33,33 @@ impl Vec3 {
 
     pub fn dot(&self, other: Vec3) -> f32 { self.x() * other.x() + self.y() * other.y() + self.z() * other.z() }
 
+    pub fn cross(&self, other: Vec3) -> Vec3 {
+        Vec3::new(
+            self.y() * other.z() - self.z() * other.y(),
+            self.z() * other.x() - self.x() * other.z(),
+            self.x() * other.y() - self.y() * other.x(),
+        )
+    }
+
     pub fn sqrt(&self) -> Vec3 { Vec3(self.0.sqrt()) }
 
     pub fn element_mul(&self, other: Vec3) -> Vec3 { Vec3(self.0 * other.0) }
 
+    // built from the components, dividing the unused lane would make it NaN
+    pub fn element_div(&self, other: Vec3) -> Vec3 {
+        Vec3::new(self.x() / other.x(), self.y() / other.y(), self.z() / other.z())
+    }
+
+    pub fn abs(&self) -> Vec3 { Vec3(self.0.abs()) }
+
+    pub fn min(&self, other: Vec3) -> Vec3 { Vec3(self.0.simd_min(other.0)) }
+
+    pub fn max(&self, other: Vec3) -> Vec3 { Vec3(self.0.simd_max(other.0)) }
+
+    pub fn min_element(&self) -> f32 { self.x().min(self.y()).min(self.z()) }
+
+    pub fn max_element(&self) -> f32 { self.x().max(self.y()).max(self.z()) }
+
     pub fn reflect(&self, normal: Vec3) -> Vec3 { *self - normal * (2.0 * normal.dot(*self)) }
 
     pub fn refract(&self, normal: Vec3, ior: f32) -> Vec3 {
@@ -70,6 +95,10 @@ impl Sub for Vec3 {
     fn sub(self, rhs: Self) -> Self::Output { Vec3(self.0 - rhs.0) }
 }
 
+impl SubAssign for Vec3 {
+    fn sub_assign(&mut self, rhs: Self) { self.0 -= rhs.0; }
+}
+
 impl Add<f32> for Vec3 {
     type Output = Vec3;
 
@@ -99,3 +128,274 @@ impl Mul<Vec3> for f32 {
 
     fn mul(self, rhs: Vec3) -> Self::Output { rhs * self }
 }
+
+/// 3x3 matrix, stored as columns
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Mat3(pub [Vec3; 3]);
+
+impl Mat3 {
+    pub const IDENTITY: Mat3 = Mat3::from_cols(
+        Vec3::new(1.0, 0.0, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        Vec3::new(0.0, 0.0, 1.0),
+    );
+
+    pub const fn from_cols(x: Vec3, y: Vec3, z: Vec3) -> Self { Self([x, y, z]) }
+
+    pub fn from_scale(scale: Vec3) -> Self {
+        Self::from_cols(
+            Vec3::new(scale.x(), 0.0, 0.0),
+            Vec3::new(0.0, scale.y(), 0.0),
+            Vec3::new(0.0, 0.0, scale.z()),
+        )
+    }
+
+    /// Rotation by `angle` radians about `axis`
+    pub fn from_axis_angle(axis: Vec3, angle: f32) -> Self { Quat::from_axis_angle(axis, angle).to_mat3() }
+
+    pub fn transpose(&self) -> Mat3 {
+        let [x, y, z] = self.0;
+        Self::from_cols(
+            Vec3::new(x.x(), y.x(), z.x()),
+            Vec3::new(x.y(), y.y(), z.y()),
+            Vec3::new(x.z(), y.z(), z.z()),
+        )
+    }
+
+    pub fn determinant(&self) -> f32 { self.0[0].dot(self.0[1].cross(self.0[2])) }
+
+    pub fn inverse(&self) -> Mat3 {
+        let [x, y, z] = self.0;
+        // the rows of the inverse are perpendicular to two of the columns
+        let rows = Self::from_cols(y.cross(z), z.cross(x), x.cross(y));
+        let inv_det = self.determinant().recip();
+        let [x, y, z] = rows.transpose().0;
+        Self::from_cols(x * inv_det, y * inv_det, z * inv_det)
+    }
+
+    /// The least and most the matrix stretches any vector by, its smallest and largest singular values
+    pub fn scale_bounds(&self) -> (f32, f32) {
+        // eigenvalues of the symmetric matrix MᵀM, which are the squares of the singular values
+        let [x, y, z] = self.0;
+        let (a00, a11, a22) = (x.dot(x), y.dot(y), z.dot(z));
+        let (a01, a02, a12) = (x.dot(y), x.dot(z), y.dot(z));
+
+        let off_diagonal = a01 * a01 + a02 * a02 + a12 * a12;
+        let q = (a00 + a11 + a22) / 3.0;
+        let p = (((a00 - q).powi(2) + (a11 - q).powi(2) + (a22 - q).powi(2) + 2.0 * off_diagonal) / 6.0).sqrt();
+        if p <= f32::EPSILON * q {
+            return (q.sqrt(), q.sqrt());
+        }
+
+        // determinant of (MᵀM - qI) / p
+        let (b00, b11, b22) = ((a00 - q) / p, (a11 - q) / p, (a22 - q) / p);
+        let (b01, b02, b12) = (a01 / p, a02 / p, a12 / p);
+        let det = b00 * (b11 * b22 - b12 * b12) - b01 * (b01 * b22 - b12 * b02) + b02 * (b01 * b12 - b11 * b02);
+        let phi = (det / 2.0).clamp(-1.0, 1.0).acos() / 3.0;
+
+        let largest = q + 2.0 * p * phi.cos();
+        let smallest = q + 2.0 * p * (phi + TAU / 3.0).cos();
+        (smallest.max(0.0).sqrt(), largest.max(0.0).sqrt())
+    }
+}
+
+impl Mul<Vec3> for Mat3 {
+    type Output = Vec3;
+
+    fn mul(self, rhs: Vec3) -> Self::Output { self.0[0] * rhs.x() + self.0[1] * rhs.y() + self.0[2] * rhs.z() }
+}
+
+impl Mul for Mat3 {
+    type Output = Mat3;
+
+    fn mul(self, rhs: Self) -> Self::Output { Mat3::from_cols(self * rhs.0[0], self * rhs.0[1], self * rhs.0[2]) }
+}
+
+/// 4x4 matrix for affine transforms, stored as columns. The last row is always 0 0 0 1
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Mat4(pub [f32x4; 4]);
+
+impl Mat4 {
+    pub const IDENTITY: Mat4 = Mat4::from_mat3_translation(Mat3::IDENTITY, Vec3::new(0.0, 0.0, 0.0));
+
+    pub const fn from_mat3_translation(matrix: Mat3, translation: Vec3) -> Self {
+        let [x, y, z] = matrix.0;
+        let t = translation.0.to_array();
+        Self([x.0, y.0, z.0, f32x4::from_array([t[0], t[1], t[2], 1.0])])
+    }
+
+    pub fn from_translation(translation: Vec3) -> Self { Self::from_mat3_translation(Mat3::IDENTITY, translation) }
+
+    pub fn from_scale(scale: Vec3) -> Self { Self::from_mat3_translation(Mat3::from_scale(scale), Vec3::default()) }
+
+    pub fn from_quat(rotation: Quat) -> Self { Self::from_mat3_translation(rotation.to_mat3(), Vec3::default()) }
+
+    /// Scale, then rotate, then translate
+    pub fn from_scale_rotation_translation(scale: Vec3, rotation: Quat, translation: Vec3) -> Self {
+        Self::from_mat3_translation(rotation.to_mat3() * Mat3::from_scale(scale), translation)
+    }
+
+    /// The rotation and scale part
+    pub fn mat3(&self) -> Mat3 { Mat3::from_cols(Vec3(self.0[0]), Vec3(self.0[1]), Vec3(self.0[2])) }
+
+    pub fn translation(&self) -> Vec3 {
+        let mut t = self.0[3];
+        t[3] = 0.0;
+        Vec3(t)
+    }
+
+    pub fn transform_point(&self, point: Vec3) -> Vec3 { self.mat3() * point + self.translation() }
+
+    pub fn transform_vector(&self, vector: Vec3) -> Vec3 { self.mat3() * vector }
+
+    pub fn inverse(&self) -> Mat4 {
+        let inverse = self.mat3().inverse();
+        Self::from_mat3_translation(inverse, -(inverse * self.translation()))
+    }
+}
+
+impl Mul for Mat4 {
+    type Output = Mat4;
+
+    fn mul(self, rhs: Self) -> Self::Output {
+        Self::from_mat3_translation(self.mat3() * rhs.mat3(), self.transform_point(rhs.translation()))
+    }
+}
+
+/// Rotation quaternion, `x`, `y` and `z` are the vector part
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quat(pub f32x4);
+
+impl Quat {
+    pub const IDENTITY: Quat = Quat::new(0.0, 0.0, 0.0, 1.0);
+
+    pub const fn new(x: f32, y: f32, z: f32, w: f32) -> Self { Self(f32x4::from_array([x, y, z, w])) }
+
+    /// Rotation by `angle` radians about `axis`, anticlockwise looking down the axis
+    pub fn from_axis_angle(axis: Vec3, angle: f32) -> Self {
+        let (sin, cos) = (angle * 0.5).sin_cos();
+        let axis = axis.normalized() * sin;
+        Self::new(axis.x(), axis.y(), axis.z(), cos)
+    }
+
+    #[inline(always)]
+    pub fn w(&self) -> f32 { self.0[3] }
+
+    /// The vector part
+    pub fn xyz(&self) -> Vec3 {
+        let mut v = self.0;
+        v[3] = 0.0;
+        Vec3(v)
+    }
+
+    pub fn normalized(&self) -> Quat { Quat(self.0 / f32x4::splat((self.0 * self.0).reduce_sum().sqrt())) }
+
+    /// The opposite rotation, for a normalised quaternion
+    pub fn conjugate(&self) -> Quat { Quat(self.0 * f32x4::from_array([-1.0, -1.0, -1.0, 1.0])) }
+
+    pub fn rotate(&self, v: Vec3) -> Vec3 {
+        let q = self.xyz();
+        let t = 2.0 * q.cross(v);
+        v + t * self.w() + q.cross(t)
+    }
+
+    pub fn to_mat3(&self) -> Mat3 {
+        Mat3::from_cols(
+            self.rotate(Vec3::new(1.0, 0.0, 0.0)),
+            self.rotate(Vec3::new(0.0, 1.0, 0.0)),
+            self.rotate(Vec3::new(0.0, 0.0, 1.0)),
+        )
+    }
+}
+
+impl Mul for Quat {
+    type Output = Quat;
+
+    /// `rhs` first, then `self`
+    fn mul(self, rhs: Self) -> Self::Output {
+        let (a, b) = (self.xyz(), rhs.xyz());
+        let v = b * self.w() + a * rhs.w() + a.cross(b);
+        Quat::new(v.x(), v.y(), v.z(), self.w() * rhs.w() - a.dot(b))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: Vec3, b: Vec3) { assert!((a - b).mag() < 1e-5, "{} != {}", a, b) }
+
+    fn assert_mat3_close(a: Mat3, b: Mat3) {
+        for (&a, &b) in a.0.iter().zip(&b.0) {
+            assert_close(a, b);
+        }
+    }
+
+    fn skewed() -> Mat3 {
+        Mat3::from_cols(
+            Vec3::new(2.0, 0.5, -1.0),
+            Vec3::new(0.3, 1.5, 0.2),
+            Vec3::new(-0.4, 0.1, 3.0),
+        )
+    }
+
+    #[test]
+    fn inverses() {
+        let m = skewed();
+        assert_mat3_close(m * m.inverse(), Mat3::IDENTITY);
+        assert_mat3_close(m.inverse() * m, Mat3::IDENTITY);
+
+        let transform = Mat4::from_mat3_translation(m, Vec3::new(1.0, -2.0, 5.0));
+        let point = Vec3::new(0.7, 0.2, -3.0);
+        assert_close(
+            transform.inverse().transform_point(transform.transform_point(point)),
+            point,
+        );
+        let identity = transform * transform.inverse();
+        assert_mat3_close(identity.mat3(), Mat3::IDENTITY);
+        assert_close(identity.translation(), Vec3::default());
+    }
+
+    #[test]
+    fn rotations() {
+        let angle = 30f32.to_radians();
+        let (sin, cos) = angle.sin_cos();
+        let about_z = Mat3::from_cols(
+            Vec3::new(cos, sin, 0.0),
+            Vec3::new(-sin, cos, 0.0),
+            Vec3::new(0.0, 0.0, 1.0),
+        );
+        assert_mat3_close(Mat3::from_axis_angle(Vec3::new(0.0, 0.0, 2.0), angle), about_z);
+
+        // a quarter turn about y takes z to x
+        let quarter = Quat::from_axis_angle(Vec3::new(0.0, 1.0, 0.0), 90f32.to_radians());
+        assert_close(quarter.rotate(Vec3::new(0.0, 0.0, 1.0)), Vec3::new(1.0, 0.0, 0.0));
+
+        let q = Quat::from_axis_angle(Vec3::new(1.0, 2.0, 3.0), 1.2);
+        let r = Quat::from_axis_angle(Vec3::new(-1.0, 0.0, 0.5), 0.4);
+        let v = Vec3::new(0.3, -0.8, 2.0);
+        assert_close(q.rotate(v), q.to_mat3() * v);
+        assert_close((q * r).rotate(v), q.rotate(r.rotate(v)));
+        assert_mat3_close((q * r).to_mat3(), q.to_mat3() * r.to_mat3());
+        assert_close(q.conjugate().rotate(q.rotate(v)), v);
+        assert_mat3_close(q.to_mat3().inverse(), q.to_mat3().transpose());
+    }
+
+    #[test]
+    fn scale_bounds() {
+        let (min, max) = Mat3::from_scale(Vec3::new(0.5, 3.0, -2.0)).scale_bounds();
+        assert!((min - 0.5).abs() < 1e-5 && (max - 3.0).abs() < 1e-5);
+
+        let (min, max) = Mat3::from_axis_angle(Vec3::new(1.0, 1.0, 0.0), 0.7).scale_bounds();
+        assert!((min - 1.0).abs() < 1e-5 && (max - 1.0).abs() < 1e-5);
+
+        // no vector is stretched by more or less than the bounds
+        let m = skewed();
+        let (min, max) = m.scale_bounds();
+        for i in 0..100 {
+            let v = Quat::from_axis_angle(Vec3::new(1.0, 0.3, -0.2), i as f32 * 0.37).rotate(Vec3::new(0.6, 0.0, 0.8));
+            let length = (m * v).mag();
+            assert!(length >= min - 1e-5 && length <= max + 1e-5);
+        }
+    }
+}